@@ -1,14 +1,19 @@
+#[cfg(feature = "libwz")]
 extern crate bindgen;
 
+#[cfg(feature = "libwz")]
 use std::{
     env,
     path::{Path, PathBuf},
-    process::Command,
 };
 
+#[cfg(not(feature = "libwz"))]
+fn main() {}
+
+#[cfg(feature = "libwz")]
 fn main() {
-    if !Path::new("wz/.git").exists() {
-        let _ = Command::new("git").args(&["submodule", "update", "--init"]).status();
+    if !Path::new("libwz/src/wz.h").exists() {
+        panic!("libwz sources are missing, run `git submodule update --init` or use `default-features = false, features = [\"pure\"]`");
     }
 
     let mut cfg = cc::Build::new();
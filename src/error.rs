@@ -0,0 +1,101 @@
+use crate::node::{Dtype, MapleNode};
+use std::fmt::{Display, Formatter};
+
+pub type Result<T, E = WzError> = std::result::Result<T, E>;
+
+/// Errors returned by the [`MapleNode`](crate::node::MapleNode) API.
+/// Every variant carries the path of the node (or file) it occurred on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WzError {
+    /// The node has a different [`Dtype`] than the accessor expects.
+    /// `actual` is [`None`] when the type of node could not be read.
+    TypeMismatch { path: String, expected: Dtype, actual: Option<Dtype> },
+    /// No node exists at the path.
+    NotFound { path: String },
+    /// The child index is out of range.
+    IndexOutOfRange { path: String, index: u32, len: u32 },
+    /// The number of the node does not fit in the Rust type `target` it is read as.
+    ValueOutOfRange { path: String, value: i64, target: &'static str },
+    /// A string of the node could not be decrypted, which usually means a wrong key.
+    Decryption { path: String },
+    /// The compressed payload of the node is not a valid zlib stream.
+    Decompression { path: String },
+    /// The [`Dtype::UOL`] link of the node points to no node.
+    BrokenLink { path: String, link: String },
+    /// The [`Dtype::UOL`] links starting at the node form a cycle.
+    CyclicLink { path: String },
+    /// The path contains an interior nul byte.
+    InvalidPath { path: String },
+    /// The query passed to [`MapleNode::select`](crate::node::MapleNode::select) does not parse.
+    InvalidQuery { query: String, reason: String },
+    /// The file is not open, because it does not exist or is not a wz file.
+    FileNotOpen { path: String },
+    /// The context could not be initialized.
+    ContextInit,
+    /// The data of the node (or file) is truncated or malformed.
+    Corrupt { path: String },
+    /// The canvas or sound of the node has a pixel format or codec which can not be decoded.
+    UnsupportedFormat { path: String, format: u32 },
+    /// The payload of the node could not be decoded, e.g. the MPEG frames of a sound.
+    Decode { path: String, reason: String },
+    /// Writing a decoded asset to the file at `path` failed.
+    Export { path: String, reason: String },
+    /// The type tag of the node is not a [`Dtype`].
+    UnknownType { path: String, tag: u8 },
+    /// The libwz function `call` failed on the node, although its type was accepted.
+    Ffi { path: String, call: &'static str },
+}
+
+impl WzError {
+    pub(crate) fn mismatch<N: MapleNode + ?Sized>(node: &N, expected: Dtype) -> WzError {
+        WzError::TypeMismatch { path: node.path().to_owned(), expected, actual: node.dtype().ok() }
+    }
+
+    /// Fill in the path of an error made without one, e.g. by [`ImageBuffer::to_rgba8`](crate::node::ImageBuffer::to_rgba8).
+    #[cfg(feature = "image")]
+    pub(crate) fn with_path(self, node_path: &str) -> WzError {
+        match self {
+            WzError::Corrupt { path } if path.is_empty() => WzError::Corrupt { path: node_path.to_owned() },
+            WzError::UnsupportedFormat { path, format } if path.is_empty() => {
+                WzError::UnsupportedFormat { path: node_path.to_owned(), format }
+            }
+            e => e,
+        }
+    }
+}
+
+impl Display for WzError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WzError::TypeMismatch { path, expected, actual } => {
+                let actual = actual.map(|t| t.to_str()).unwrap_or("unknown");
+                write!(f, "node [{}] has type {}, expected {}", path, actual, expected.to_str())
+            }
+            WzError::NotFound { path } => write!(f, "node [{}] not found", path),
+            WzError::IndexOutOfRange { path, index, len } => {
+                write!(f, "child {} of node [{}] out of range (len {})", index, path, len)
+            }
+            WzError::ValueOutOfRange { path, value, target } => {
+                write!(f, "value {} of node [{}] does not fit in {}", value, path, target)
+            }
+            WzError::Decryption { path } => write!(f, "failed to decrypt node [{}]", path),
+            WzError::Decompression { path } => write!(f, "corrupt zlib stream in node [{}]", path),
+            WzError::BrokenLink { path, link } => write!(f, "uol [{}] links to missing node [{}]", path, link),
+            WzError::CyclicLink { path } => write!(f, "uol [{}] is part of a cycle", path),
+            WzError::InvalidPath { path } => write!(f, "invalid path [{}]", path),
+            WzError::InvalidQuery { query, reason } => write!(f, "invalid query [{}]: {}", query, reason),
+            WzError::FileNotOpen { path } => write!(f, "file [{}] is not open", path),
+            WzError::ContextInit => write!(f, "failed to initialize wz context"),
+            WzError::Corrupt { path } => write!(f, "corrupt data in [{}]", path),
+            WzError::UnsupportedFormat { path, format } => {
+                write!(f, "node [{}] has unsupported format {}", path, format)
+            }
+            WzError::Decode { path, reason } => write!(f, "failed to decode node [{}]: {}", path, reason),
+            WzError::Export { path, reason } => write!(f, "failed to write [{}]: {}", path, reason),
+            WzError::UnknownType { path, tag } => write!(f, "node [{}] has unknown type tag {}", path, tag),
+            WzError::Ffi { path, call } => write!(f, "{} failed on node [{}]", call, path),
+        }
+    }
+}
+
+impl std::error::Error for WzError {}
@@ -0,0 +1,326 @@
+use crate::{
+    error::{Result, WzError},
+    frame::Animation,
+};
+use image::{codecs::webp::WebPEncoder, imageops, ExtendedColorType, RgbaImage};
+use std::{
+    convert::{TryFrom, TryInto},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+impl Animation {
+    /// Draw the frames of one cycle, see [`Animation::sequence`], on a common canvas with their delays.
+    ///
+    /// The canvas is the smallest one holding every frame with its origin at the same point.
+    /// Frames with a zero delay are skipped, and the alpha of [`Frame::alpha`](crate::frame::Frame::alpha)
+    /// is not applied. Fail with [`WzError::Export`] with an empty path when no frame remains.
+    pub fn compose(&self) -> Result<Vec<(RgbaImage, u32)>> {
+        let bounds = self.frames.iter().map(|frame| {
+            let (x, y) = (-frame.origin.x.round() as i64, -frame.origin.y.round() as i64);
+            (x, y, x + frame.image.width as i64, y + frame.image.height as i64)
+        });
+        let (left, top, right, bottom) =
+            bounds.reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))).unwrap_or_default();
+        let (width, height) = ((right - left) as u32, (bottom - top) as u32);
+        let mut composed = vec![];
+        for i in self.sequence() {
+            let frame = &self.frames[i];
+            if frame.delay == 0 {
+                continue;
+            }
+            let mut canvas = RgbaImage::new(width, height);
+            let (x, y) = (-frame.origin.x.round() as i64 - left, -frame.origin.y.round() as i64 - top);
+            imageops::replace(&mut canvas, &frame.image.to_rgba_image()?, x, y);
+            composed.push((canvas, frame.delay));
+        }
+        if composed.is_empty() || width == 0 || height == 0 {
+            return Err(export_error("", "no frame to export"));
+        }
+        Ok(composed)
+    }
+
+    /// Save the frames as an animated GIF, see [`Animation::compose`].
+    /// Colors are quantized to 256 per frame, and alpha to fully transparent or opaque.
+    /// Delays are rounded to the nearest 10 milliseconds, the unit of GIF, and are at least 10.
+    pub fn save_gif<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let frames = self.compose().map_err(|e| with_path(e, path))?;
+        let (width, height) = frames[0].0.dimensions();
+        let (width, height) = match (u16::try_from(width), u16::try_from(height)) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => return Err(export_error(path, format!("{}x{} is too large for GIF", width, height))),
+        };
+        let write = || -> std::result::Result<(), gif::EncodingError> {
+            let mut encoder = gif::Encoder::new(BufWriter::new(File::create(path)?), width, height, &[])?;
+            encoder.set_repeat(if self.looping { gif::Repeat::Infinite } else { gif::Repeat::Finite(0) })?;
+            for (canvas, delay) in frames {
+                let mut pixels = canvas.into_raw();
+                let mut frame = gif::Frame::from_rgba_speed(width, height, &mut pixels, 10);
+                frame.delay = gif_delay(delay);
+                frame.dispose = gif::DisposalMethod::Background;
+                encoder.write_frame(&frame)?;
+            }
+            Ok(())
+        };
+        write().map_err(|e| export_error(path, e))
+    }
+
+    /// Save the frames as an animated PNG, see [`Animation::compose`].
+    pub fn save_apng<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let frames = self.compose().map_err(|e| with_path(e, path))?;
+        let (width, height) = frames[0].0.dimensions();
+        let write = || -> std::result::Result<(), png::EncodingError> {
+            let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(frames.len() as u32, if self.looping { 0 } else { 1 })?;
+            let mut writer = encoder.write_header()?;
+            for (canvas, delay) in &frames {
+                writer.set_frame_delay((*delay).min(u16::MAX as u32) as u16, 1000)?;
+                writer.write_image_data(canvas)?;
+            }
+            writer.finish()
+        };
+        write().map_err(|e| export_error(path, e))
+    }
+
+    /// Save the frames as a lossless animated WebP, see [`Animation::compose`].
+    /// Fail with [`WzError::Export`] when the canvas is larger than 16384 in either dimension.
+    pub fn save_webp<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let frames = self.compose().map_err(|e| with_path(e, path))?;
+        let riff = webp(&frames, self.looping).map_err(|e| export_error(path, e))?;
+        File::create(path).and_then(|mut file| file.write_all(&riff)).map_err(|e| export_error(path, e))
+    }
+}
+
+/// The largest width and height of a lossless WebP, whose header stores them in 14 bits.
+const WEBP_MAX_SIZE: u32 = 16384;
+
+/// Convert a delay in milliseconds to the nearest hundredths of a second of GIF, at least one.
+fn gif_delay(delay: u32) -> u16 {
+    (delay.saturating_add(5) / 10).clamp(1, u16::MAX as u32) as u16
+}
+
+/// Mux frames of the same size with their delays into an animated WebP, each frame encoded as a lossless still.
+/// Fail when the frames are larger than [`WEBP_MAX_SIZE`] in either dimension.
+fn webp(frames: &[(RgbaImage, u32)], looping: bool) -> std::result::Result<Vec<u8>, String> {
+    let (width, height) = frames[0].0.dimensions();
+    if width > WEBP_MAX_SIZE || height > WEBP_MAX_SIZE {
+        return Err(format!("{}x{} is too large for WebP", width, height));
+    }
+    let mut body = b"WEBP".to_vec();
+    let mut vp8x = vec![0x12, 0, 0, 0];
+    vp8x.extend_from_slice(&u24(width - 1));
+    vp8x.extend_from_slice(&u24(height - 1));
+    chunk(&mut body, b"VP8X", &vp8x);
+    let repeat: u16 = if looping { 0 } else { 1 };
+    chunk(&mut body, b"ANIM", &[&[0u8; 4][..], &repeat.to_le_bytes()].concat());
+    for (canvas, delay) in frames {
+        let mut still = vec![];
+        WebPEncoder::new_lossless(&mut still)
+            .encode(canvas, width, height, ExtendedColorType::Rgba8)
+            .map_err(|e| e.to_string())?;
+        let vp8l = find_chunk(&still, b"VP8L").ok_or("no VP8L chunk encoded")?;
+        // Offset 0 x 0, the size of the canvas, the delay, then no blending with the previous frame.
+        let mut anmf = vec![0u8; 6];
+        anmf.extend_from_slice(&u24(width - 1));
+        anmf.extend_from_slice(&u24(height - 1));
+        anmf.extend_from_slice(&u24((*delay).min(0xFF_FFFF)));
+        anmf.push(0x02);
+        chunk(&mut anmf, b"VP8L", vp8l);
+        chunk(&mut body, b"ANMF", &anmf);
+    }
+    let mut riff = vec![];
+    chunk(&mut riff, b"RIFF", &body);
+    Ok(riff)
+}
+
+fn export_error<P: AsRef<Path>, E: ToString>(path: P, reason: E) -> WzError {
+    WzError::Export { path: path.as_ref().display().to_string(), reason: reason.to_string() }
+}
+
+/// Fill the path of an [`WzError::Export`] from [`Animation::compose`].
+fn with_path(e: WzError, path: &Path) -> WzError {
+    match e {
+        WzError::Export { reason, .. } => export_error(path, reason),
+        e => e,
+    }
+}
+
+fn u24(v: u32) -> [u8; 3] {
+    let [a, b, c, _] = v.to_le_bytes();
+    [a, b, c]
+}
+
+/// Append a RIFF chunk, padded to an even size.
+fn chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// Find the data of the first chunk with given fourcc in a RIFF WebP file.
+fn find_chunk<'a>(riff: &'a [u8], fourcc: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut rest = riff.get(12..)?;
+    while rest.len() >= 8 {
+        let len = u32::from_le_bytes(rest[4..8].try_into().ok()?) as usize;
+        let data = rest.get(8..8 + len)?;
+        if &rest[..4] == fourcc {
+            return Some(data);
+        }
+        rest = rest.get(8 + len + len % 2..)?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{frame::Frame, node::ImageBuffer};
+    use glam::Vec2;
+    use std::{collections::HashMap, io::BufReader};
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    /// A frame of one RGBA color.
+    fn frame(width: u32, height: u32, [r, g, b, a]: [u8; 4], origin: Vec2, delay: u32) -> Frame {
+        let data = [b, g, r, a].repeat(width as usize * height as usize).into();
+        let image = ImageBuffer { data, width, height, depth: 2, scale: 0 };
+        Frame { image, origin, head: None, lt: None, rb: None, map: HashMap::new(), delay, z: None, alpha: (255, 255) }
+    }
+
+    /// A red 2x2 frame shown for 100ms, then a blue one with its origin one pixel further for 50ms.
+    fn animation(looping: bool) -> Animation {
+        let frames = vec![frame(2, 2, RED, Vec2::ZERO, 100), frame(2, 2, BLUE, Vec2::ONE, 50)];
+        Animation { frames, zigzag: false, looping }
+    }
+
+    fn temp(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("wz-export-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn compose() {
+        let mut animation = animation(true);
+        animation.frames.push(frame(1, 1, RED, Vec2::ZERO, 0));
+        let composed = animation.compose().unwrap();
+        let delays: Vec<u32> = composed.iter().map(|(_, delay)| *delay).collect();
+        assert_eq!(delays, [100, 50]);
+        // the origins meet at (1, 1) of a 3x3 canvas
+        let (red, blue) = (&composed[0].0, &composed[1].0);
+        assert_eq!((red.dimensions(), blue.dimensions()), ((3, 3), (3, 3)));
+        assert_eq!([red.get_pixel(0, 0).0, red.get_pixel(1, 1).0, red.get_pixel(2, 2).0], [[0; 4], RED, RED]);
+        assert_eq!([blue.get_pixel(0, 0).0, blue.get_pixel(1, 1).0, blue.get_pixel(2, 2).0], [BLUE, BLUE, [0; 4]]);
+        animation.frames.clear();
+        assert!(matches!(animation.compose(), Err(WzError::Export { .. })));
+    }
+
+    #[test]
+    fn gif() {
+        let path = temp("anim.gif");
+        for (looping, repeat) in [(true, gif::Repeat::Infinite), (false, gif::Repeat::Finite(0))] {
+            animation(looping).save_gif(&path).unwrap();
+            let mut options = gif::DecodeOptions::new();
+            options.set_color_output(gif::ColorOutput::RGBA);
+            let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+            let mut frames = vec![];
+            while let Some(frame) = decoder.read_next_frame().unwrap() {
+                frames.push((frame.delay, frame.buffer[4 * 4..4 * 5].to_vec()));
+            }
+            assert_eq!(frames, [(10, RED.to_vec()), (5, BLUE.to_vec())]);
+            assert_eq!(decoder.repeat(), repeat);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn apng() {
+        let path = temp("anim.png");
+        for (looping, plays) in [(true, 0), (false, 1)] {
+            animation(looping).save_apng(&path).unwrap();
+            let mut reader = png::Decoder::new(BufReader::new(File::open(&path).unwrap())).read_info().unwrap();
+            let control = reader.info().animation_control().unwrap();
+            assert_eq!((control.num_frames, control.num_plays), (2, plays));
+            let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+            let mut frames = vec![];
+            for _ in 0..2 {
+                reader.next_frame(&mut buf).unwrap();
+                let control = reader.info().frame_control().unwrap();
+                frames.push((control.delay_num, control.delay_den, buf[4 * 4..4 * 5].to_vec()));
+            }
+            assert_eq!(frames, [(100, 1000, RED.to_vec()), (50, 1000, BLUE.to_vec())]);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn webp_too_large() {
+        let path = temp("large.webp");
+        let animation =
+            Animation { frames: vec![frame(WEBP_MAX_SIZE + 1, 1, RED, Vec2::ZERO, 100)], ..animation(true) };
+        let err = animation.save_webp(&path).unwrap_err();
+        let reason = "16385x1 is too large for WebP".to_owned();
+        assert_eq!(err, WzError::Export { path: path.display().to_string(), reason });
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn gif_delays() {
+        assert_eq!(gif_delay(1), 1);
+        assert_eq!(gif_delay(14), 1);
+        assert_eq!(gif_delay(15), 2);
+        assert_eq!(gif_delay(120), 12);
+        assert_eq!(gif_delay(u32::MAX), u16::MAX);
+    }
+
+    #[test]
+    fn chunk_padding() {
+        let mut out = vec![];
+        chunk(&mut out, b"ABCD", &[1, 2, 3]);
+        assert_eq!(out, [b'A', b'B', b'C', b'D', 3, 0, 0, 0, 1, 2, 3, 0]);
+        chunk(&mut out, b"EFGH", &[4, 5]);
+        assert_eq!(out.len(), 12 + 10);
+    }
+
+    /// Split the chunks of data, checking that their sizes and padding add up to its length.
+    fn chunks(mut data: &[u8]) -> Vec<(&[u8], &[u8])> {
+        let mut chunks = vec![];
+        while !data.is_empty() {
+            let len = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+            chunks.push((&data[..4], &data[8..8 + len]));
+            data = &data[8 + len + len % 2..];
+        }
+        chunks
+    }
+
+    #[test]
+    fn webp_mux() {
+        let frames: Vec<(RgbaImage, u32)> =
+            (0..2u8).map(|i| (RgbaImage::from_pixel(3, 2, image::Rgba([i, 2, 3, 255])), 100 + i as u32)).collect();
+        let riff = webp(&frames, true).unwrap();
+        let riff = chunks(&riff);
+        assert_eq!(riff.len(), 1);
+        assert_eq!(riff[0].0, b"RIFF");
+        assert_eq!(&riff[0].1[..4], b"WEBP");
+        let body = chunks(&riff[0].1[4..]);
+        let fourccs: Vec<&[u8]> = body.iter().map(|(fourcc, _)| *fourcc).collect();
+        assert_eq!(fourccs, [b"VP8X", b"ANIM", b"ANMF", b"ANMF"]);
+        assert_eq!(body[0].1, [0x12, 0, 0, 0, 2, 0, 0, 1, 0, 0]);
+        assert_eq!(body[1].1, [0, 0, 0, 0, 0, 0]);
+        for (i, (_, anmf)) in body[2..].iter().enumerate() {
+            assert_eq!(anmf[..16], [0, 0, 0, 0, 0, 0, 2, 0, 0, 1, 0, 0, 100 + i as u8, 0, 0, 0x02]);
+            let frame = chunks(&anmf[16..]);
+            assert_eq!(frame.len(), 1);
+            assert_eq!(frame[0].0, b"VP8L");
+        }
+        assert_eq!(chunks(&webp(&frames, false).unwrap()[12..])[1].1, [0, 0, 0, 0, 1, 0]);
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{
+    error::Result,
+    from_node::{find_child, FromWzNode},
+    node::{Dtype, ImageBuffer, MapleNode},
+};
+use glam::Vec2;
+use std::collections::HashMap;
+
+/// The delay of a frame without a `delay` child, in milliseconds.
+pub const DEFAULT_DELAY: u32 = 100;
+
+/// A canvas with the metadata of its children, read by [`Frame::from_node`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    /// The pixels of the canvas, copied out of the file.
+    pub image: ImageBuffer<'static>,
+    /// The point of the bitmap placed at the position of its owner, from its top left corner. Zero when missing.
+    pub origin: Vec2,
+    /// The `head` anchor, relative to the origin.
+    pub head: Option<Vec2>,
+    /// The left top corner of the bounds (`lt`), relative to the origin.
+    pub lt: Option<Vec2>,
+    /// The right bottom corner of the bounds (`rb`), relative to the origin.
+    pub rb: Option<Vec2>,
+    /// The named anchors of the `map` child, e.g. `navel` or `hand` on character parts.
+    pub map: HashMap<String, Vec2>,
+    /// How long the frame is shown in milliseconds, [`DEFAULT_DELAY`] when missing.
+    pub delay: u32,
+    /// The drawing order, [`None`] when missing.
+    pub z: Option<ZOrder>,
+    /// The alpha at the start and end of the delay (`a0` and `a1`), interpolated in between.
+    /// Both are 255 when missing, and `a1` is `a0` when only `a0` is present.
+    pub alpha: (u8, u8),
+}
+
+/// The `z` child of a frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ZOrder {
+    /// A number, e.g. on map objects, lower drawn first.
+    Index(i32),
+    /// A named layer, e.g. `armOverHair` on character parts.
+    Layer(String),
+}
+
+impl Frame {
+    /// Read the frame of a node with type [`Dtype::IMG`], or a [`Dtype::UOL`] linking to one.
+    pub fn from_node<N: MapleNode + ?Sized>(node: &N) -> Result<Frame>
+    where
+        N::Item: MapleNode<Item = N::Item>,
+    {
+        if node.dtype()? == Dtype::UOL {
+            return Frame::from_node(&*node.resolve()?);
+        }
+        let image = node.img()?.into_owned();
+        let vec = |name| find_child(node, name)?.map(|child| child.vec()).transpose();
+        let map = match find_child(node, "map")? {
+            Some(map) => FromWzNode::from_wz_node(&*map)?,
+            None => HashMap::new(),
+        };
+        let delay = match find_child(node, "delay")? {
+            Some(delay) => FromWzNode::from_wz_node(&*delay)?,
+            None => DEFAULT_DELAY,
+        };
+        let z = match find_child(node, "z")? {
+            Some(z) if z.dtype()? == Dtype::STR => Some(ZOrder::Layer(z.str_owned()?)),
+            Some(z) => Some(ZOrder::Index(z.as_i32()?)),
+            None => None,
+        };
+        let alpha = |name| find_child(node, name)?.map(|child| alpha(&*child)).transpose();
+        let a0 = alpha("a0")?.unwrap_or(255);
+        let alpha = (a0, alpha("a1")?.unwrap_or(a0));
+        Ok(Frame {
+            image,
+            origin: vec("origin")?.unwrap_or(Vec2::ZERO),
+            head: vec("head")?,
+            lt: vec("lt")?,
+            rb: vec("rb")?,
+            map,
+            delay,
+            z,
+            alpha,
+        })
+    }
+
+    /// Get the alpha `elapsed` milliseconds into the frame, interpolated from `a0` to `a1`.
+    pub fn alpha_at(&self, elapsed: u32) -> u8 {
+        let (a0, a1) = (self.alpha.0 as i64, self.alpha.1 as i64);
+        if self.delay == 0 {
+            return self.alpha.1;
+        }
+        let t = elapsed.min(self.delay) as i64;
+        (a0 + (a1 - a0) * t / self.delay as i64) as u8
+    }
+}
+
+/// Read an alpha clamped to 0..=255.
+fn alpha<N: MapleNode + ?Sized>(node: &N) -> Result<u8> {
+    Ok(node.as_i64()?.clamp(0, 255) as u8)
+}
+
+impl FromWzNode for Frame {
+    fn from_wz_node<N: MapleNode + ?Sized>(node: &N) -> Result<Self>
+    where
+        N::Item: MapleNode<Item = N::Item>,
+    {
+        Frame::from_node(node)
+    }
+}
+
+/// The frames of an animation node, whose children "0", "1", "2"… are canvases, read by [`Animation::from_node`].
+///
+/// One cycle plays the frames in order, and back to the second when `zigzag`,
+/// e.g. 0 1 2 1 for three frames. The cycle repeats when `looping`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+    /// The frames in the numeric order of their names.
+    pub frames: Vec<Frame>,
+    /// Whether the frames play back and forth, set by a non-zero `zigzag` child.
+    pub zigzag: bool,
+    /// Whether the cycle repeats, set by a non-zero `repeat` child.
+    /// Many looping animations (e.g. mob `stand`) have no such child, set it where the client loops them.
+    pub looping: bool,
+}
+
+impl Animation {
+    /// Read the frames of a node from its children with numeric names, [`Dtype::IMG`] or [`Dtype::UOL`]s
+    /// linking to one, in numeric order rather than file order. Other children are ignored.
+    pub fn from_node<N: MapleNode + ?Sized>(node: &N) -> Result<Animation>
+    where
+        N::Item: MapleNode<Item = N::Item>,
+    {
+        if node.dtype()? == Dtype::UOL {
+            return Animation::from_node(&*node.resolve()?);
+        }
+        let mut numbered = vec![];
+        for i in 0..node.len() {
+            let child = node.child_at(i)?;
+            if let Ok(number) = child.name()?.parse::<u32>() {
+                numbered.push((number, child));
+            }
+        }
+        numbered.sort_by_key(|(number, _)| *number);
+        let frames = numbered.iter().map(|(_, child)| Frame::from_node(&**child)).collect::<Result<_>>()?;
+        let flag = |name| find_child(node, name)?.map(|child| child.as_i64()).transpose();
+        Ok(Animation { frames, zigzag: flag("zigzag")?.unwrap_or(0) != 0, looping: flag("repeat")?.unwrap_or(0) != 0 })
+    }
+
+    /// Get the indices of the frames played in one cycle, in order.
+    pub fn sequence(&self) -> Vec<usize> {
+        let forward = 0..self.frames.len();
+        if self.zigzag && self.frames.len() > 2 {
+            forward.chain((1..self.frames.len() - 1).rev()).collect()
+        } else {
+            forward.collect()
+        }
+    }
+
+    /// Get the duration of one cycle in milliseconds.
+    pub fn duration(&self) -> u32 {
+        self.sequence().into_iter().map(|i| self.frames[i].delay).fold(0u32, u32::saturating_add)
+    }
+
+    /// Get the start of each frame played in one cycle in milliseconds, with the index of the frame.
+    pub fn timeline(&self) -> Vec<(u32, usize)> {
+        let mut start = 0;
+        let mut timeline = vec![];
+        for i in self.sequence() {
+            timeline.push((start, i));
+            start = self.frames[i].delay.saturating_add(start);
+        }
+        timeline
+    }
+
+    /// Get the index of the frame shown `ms` milliseconds after the start, and how long it has been shown,
+    /// e.g. for [`Frame::alpha_at`]. Return [`None`] when there are no frames or the animation ended.
+    pub fn position_at(&self, ms: u32) -> Option<(usize, u32)> {
+        let duration = self.duration();
+        let ms = match duration {
+            0 => return self.sequence().last().map(|&i| (i, 0)),
+            _ if self.looping => ms % duration,
+            _ if ms >= duration => return None,
+            _ => ms,
+        };
+        let (start, i) = self.timeline().into_iter().take_while(|&(start, _)| start <= ms).last()?;
+        Some((i, ms - start))
+    }
+
+    /// Get the frame shown `ms` milliseconds after the start, see [`Animation::position_at`].
+    pub fn frame_at(&self, ms: u32) -> Option<&Frame> {
+        self.position_at(ms).map(|(i, _)| &self.frames[i])
+    }
+}
+
+impl FromWzNode for Animation {
+    fn from_wz_node<N: MapleNode + ?Sized>(node: &N) -> Result<Self>
+    where
+        N::Item: MapleNode<Item = N::Item>,
+    {
+        Animation::from_node(node)
+    }
+}
+
+#[cfg(all(test, feature = "pure"))]
+mod tests {
+    use super::*;
+    use crate::pure::fixture;
+
+    #[test]
+    fn frame() {
+        let file = fixture::open();
+        let frame = Frame::from_node(&*file.open_root().child("Test.img/canvas").unwrap()).unwrap();
+        assert_eq!(frame.origin, Vec2::new(5.0, 6.0));
+        assert_eq!(frame.head, Some(Vec2::new(1.0, -20.0)));
+        assert_eq!((frame.lt, frame.rb), (Some(Vec2::new(-3.0, -4.0)), Some(Vec2::new(3.0, 0.0))));
+        let map = [("navel", Vec2::new(0.0, -10.0)), ("hand", Vec2::new(7.0, -8.0))];
+        assert_eq!(frame.map, map.iter().map(|&(name, v)| (name.to_owned(), v)).collect());
+        assert_eq!(frame.delay, 120);
+        assert_eq!(frame.z, Some(ZOrder::Layer("armOverHair".to_owned())));
+        assert_eq!(frame.alpha, (0, 240));
+        let alphas: Vec<u8> = [0, 30, 60, 120, 500].iter().map(|&ms| frame.alpha_at(ms)).collect();
+        assert_eq!(alphas, [0, 60, 120, 240, 240]);
+        assert_eq!((frame.image.width, frame.image.height), (2, 2));
+        assert_eq!(frame.image.data[..], (0..16).collect::<Vec<u8>>()[..]);
+    }
+
+    #[test]
+    fn frame_defaults() {
+        let file = fixture::open();
+        let frame = Frame::from_node(&*file.open_root().child("Mob/Mob.img/icon").unwrap()).unwrap();
+        assert_eq!(frame.origin, Vec2::ZERO);
+        assert_eq!((frame.head, frame.lt, frame.rb), (None, None, None));
+        assert!(frame.map.is_empty());
+        assert_eq!(frame.delay, DEFAULT_DELAY);
+        assert_eq!(frame.z, Some(ZOrder::Index(3)));
+        // a1 falls back to a0
+        assert_eq!(frame.alpha, (128, 128));
+        assert_eq!(frame.alpha_at(50), 128);
+    }
+
+    #[test]
+    fn frame_of_other_types() {
+        let file = fixture::open();
+        let test = file.open_root().child("Test.img").unwrap();
+        assert!(Frame::from_node(&*test.child("sub").unwrap()).is_err());
+        assert!(Frame::from_node(&*test.child("int").unwrap()).is_err());
+    }
+
+    #[test]
+    fn animation_node() {
+        let file = fixture::open();
+        let animation = Animation::from_node(&*file.open_root().child("Mob/Mob.img/stand").unwrap()).unwrap();
+        // frames 0, 1, 2 and 10 in numeric order, 2 being the one linked to
+        let delays: Vec<u32> = animation.frames.iter().map(|frame| frame.delay).collect();
+        assert_eq!(delays, [100, 200, 100, 300]);
+        assert_eq!(animation.frames[2], animation.frames[0]);
+        assert!(animation.zigzag && animation.looping);
+        assert_eq!(animation.sequence(), [0, 1, 2, 3, 2, 1]);
+        assert_eq!(animation.duration(), 1000);
+        assert_eq!(animation.position_at(1250), Some((1, 150)));
+    }
+
+    /// An animation of three copies of the fixture icon, each shown for 100ms.
+    fn animation(zigzag: bool, looping: bool) -> Animation {
+        let file = fixture::open();
+        let frame = Frame::from_node(&*file.open_root().child("Mob/Mob.img/icon").unwrap()).unwrap();
+        Animation { frames: vec![frame; 3], zigzag, looping }
+    }
+
+    #[test]
+    fn sequence() {
+        assert_eq!(animation(false, false).sequence(), [0, 1, 2]);
+        let zigzag = animation(true, false);
+        assert_eq!(zigzag.sequence(), [0, 1, 2, 1]);
+        assert_eq!(zigzag.duration(), 400);
+        assert_eq!(zigzag.timeline(), [(0, 0), (100, 1), (200, 2), (300, 1)]);
+    }
+
+    #[test]
+    fn position() {
+        let mut animation = animation(true, false);
+        assert_eq!(animation.position_at(0), Some((0, 0)));
+        assert_eq!(animation.position_at(250), Some((2, 50)));
+        assert_eq!(animation.position_at(399), Some((1, 99)));
+        assert_eq!(animation.position_at(400), None);
+        assert!(animation.frame_at(400).is_none());
+        animation.looping = true;
+        assert_eq!(animation.position_at(400), Some((0, 0)));
+        assert_eq!(animation.position_at(650), Some((2, 50)));
+        assert_eq!(animation.frame_at(650), Some(&animation.frames[2]));
+    }
+
+    #[test]
+    fn corrupt_delays() {
+        let mut animation = animation(false, true);
+        for frame in &mut animation.frames {
+            frame.delay = u32::MAX;
+        }
+        assert_eq!(animation.duration(), u32::MAX);
+        assert_eq!(animation.position_at(5), Some((0, 5)));
+        animation.frames.clear();
+        assert_eq!((animation.duration(), animation.position_at(0)), (0, None));
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{
+    error::{Result, WzError},
+    node::{Dtype, MapleNode},
+    value::WzValue,
+};
+use std::{collections::HashMap, convert::TryFrom};
+
+/// Derive [`FromWzNode`] for a struct, reading each field from the child node of the same name.
+/// See the `wz-derive` crate for the `#[wz(rename, default, optional)]` field attributes;
+/// `Option<T>` fields read [`None`] when their child is missing, and `#[wz(optional)]` on any other type
+/// does not compile:
+///
+/// ```compile_fail
+/// #[derive(wz::from_node::FromWzNode)]
+/// struct Mob {
+///     #[wz(optional)]
+///     level: i32,
+/// }
+/// ```
+#[cfg(feature = "derive")]
+pub use wz_derive::FromWzNode;
+
+/// Types which can be read from a node and its descendants.
+///
+/// Numbers are coerced like [`MapleNode::as_i64`] and [`MapleNode::as_f64`], so that a [`Dtype::STR`]
+/// holding a number reads as one, and fail with [`WzError::ValueOutOfRange`] when they do not fit.
+/// `bool` reads any non-zero integer as true. [`Vec`] reads the children in order
+/// and [`HashMap`] the children by name.
+pub trait FromWzNode: Sized {
+    fn from_wz_node<N: MapleNode + ?Sized>(node: &N) -> Result<Self>
+    where
+        N::Item: MapleNode<Item = N::Item>;
+}
+
+/// Get the child of node at path, [`None`] when it does not exist.
+pub fn find_child<N: MapleNode + ?Sized>(node: &N, path: &str) -> Result<Option<Box<N::Item>>> {
+    match node.child(path) {
+        Ok(child) => Ok(Some(child)),
+        Err(WzError::NotFound { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+macro_rules! from_integer {
+    ($($ty:ty),*) => {$(
+        impl FromWzNode for $ty {
+            fn from_wz_node<N: MapleNode + ?Sized>(node: &N) -> Result<Self>
+            where
+                N::Item: MapleNode<Item = N::Item>,
+            {
+                let value = node.as_i64()?;
+                <$ty>::try_from(value).map_err(|_| WzError::ValueOutOfRange {
+                    path: node.path().to_owned(),
+                    value,
+                    target: stringify!($ty),
+                })
+            }
+        }
+    )*};
+}
+
+from_integer!(i8, i16, i32, i64, u8, u16, u32, u64, usize);
+
+impl FromWzNode for f32 {
+    fn from_wz_node<N: MapleNode + ?Sized>(node: &N) -> Result<Self>
+    where
+        N::Item: MapleNode<Item = N::Item>,
+    {
+        Ok(node.as_f64()? as f32)
+    }
+}
+
+impl FromWzNode for f64 {
+    fn from_wz_node<N: MapleNode + ?Sized>(node: &N) -> Result<Self>
+    where
+        N::Item: MapleNode<Item = N::Item>,
+    {
+        node.as_f64()
+    }
+}
+
+impl FromWzNode for bool {
+    fn from_wz_node<N: MapleNode + ?Sized>(node: &N) -> Result<Self>
+    where
+        N::Item: MapleNode<Item = N::Item>,
+    {
+        Ok(node.as_i64()? != 0)
+    }
+}
+
+/// Reads a [`Dtype::STR`], or a number as text.
+impl FromWzNode for String {
+    fn from_wz_node<N: MapleNode + ?Sized>(node: &N) -> Result<Self>
+    where
+        N::Item: MapleNode<Item = N::Item>,
+    {
+        match node.dtype()? {
+            Dtype::STR => node.str_owned(),
+            Dtype::I16 | Dtype::I32 | Dtype::I64 | Dtype::F32 | Dtype::F64 => Ok(node.value()?.to_string()),
+            _ => Err(WzError::mismatch(node, Dtype::STR)),
+        }
+    }
+}
+
+impl FromWzNode for glam::Vec2 {
+    fn from_wz_node<N: MapleNode + ?Sized>(node: &N) -> Result<Self>
+    where
+        N::Item: MapleNode<Item = N::Item>,
+    {
+        node.vec()
+    }
+}
+
+impl FromWzNode for WzValue {
+    fn from_wz_node<N: MapleNode + ?Sized>(node: &N) -> Result<Self>
+    where
+        N::Item: MapleNode<Item = N::Item>,
+    {
+        node.value()
+    }
+}
+
+/// Reads [`None`] for a [`Dtype::NIL`] node and `T` otherwise.
+/// A missing child fails like any other type, see `#[wz(optional)]` of the derive for that.
+impl<T: FromWzNode> FromWzNode for Option<T> {
+    fn from_wz_node<N: MapleNode + ?Sized>(node: &N) -> Result<Self>
+    where
+        N::Item: MapleNode<Item = N::Item>,
+    {
+        match node.dtype()? {
+            Dtype::NIL => Ok(None),
+            _ => T::from_wz_node(node).map(Some),
+        }
+    }
+}
+
+impl<T: FromWzNode> FromWzNode for Vec<T> {
+    fn from_wz_node<N: MapleNode + ?Sized>(node: &N) -> Result<Self>
+    where
+        N::Item: MapleNode<Item = N::Item>,
+    {
+        (0..node.len()).map(|i| T::from_wz_node(&*node.child_at(i)?)).collect()
+    }
+}
+
+impl<T: FromWzNode> FromWzNode for HashMap<String, T> {
+    fn from_wz_node<N: MapleNode + ?Sized>(node: &N) -> Result<Self>
+    where
+        N::Item: MapleNode<Item = N::Item>,
+    {
+        (0..node.len())
+            .map(|i| {
+                let child = node.child_at(i)?;
+                Ok((child.name_owned()?, T::from_wz_node(&*child)?))
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "derive", feature = "pure"))]
+mod tests {
+    use super::*;
+    use crate::pure::fixture;
+
+    fn seven() -> u16 {
+        7
+    }
+
+    #[derive(FromWzNode, Debug, PartialEq)]
+    #[wz(crate = "crate")]
+    struct Test {
+        int: i32,
+        #[wz(rename = "sub/x")]
+        x: u8,
+        #[wz(rename = "str")]
+        text: String,
+        #[wz(default)]
+        missing: i64,
+        #[wz(default = "seven")]
+        other: u16,
+        #[wz(optional)]
+        absent: Option<i32>,
+        #[wz(optional)]
+        long: Option<i64>,
+        number: Option<u32>,
+        gone: std::option::Option<u32>,
+        #[wz(default)]
+        nothing: Option<u8>,
+    }
+
+    #[derive(FromWzNode, Debug)]
+    #[wz(crate = "crate")]
+    struct Required {
+        #[allow(dead_code)]
+        gone: i32,
+    }
+
+    #[test]
+    fn derive() {
+        let file = fixture::open();
+        let test = file.open_root().unwrap().child("Test.img").unwrap();
+        let expected = Test {
+            int: 100_000,
+            x: 42,
+            text: "héllo".to_owned(),
+            missing: 0,
+            other: 7,
+            absent: None,
+            long: Some(1 << 40),
+            number: Some(42),
+            gone: None,
+            nothing: None,
+        };
+        assert_eq!(Test::from_wz_node(&*test).unwrap(), expected);
+        assert!(matches!(Required::from_wz_node(&*test), Err(WzError::NotFound { path }) if path == "/Test.img/gone"));
+    }
+
+    #[test]
+    fn out_of_range() {
+        let file = fixture::open();
+        let int = file.open_root().unwrap().child("Test.img/int").unwrap();
+        assert!(matches!(u8::from_wz_node(&*int), Err(WzError::ValueOutOfRange { value: 100_000, .. })));
+        assert_eq!(Option::<i32>::from_wz_node(&*int).unwrap(), Some(100_000));
+    }
+}
@@ -1,7 +1,31 @@
+#![cfg_attr(docsrs, feature(doc_cfg))]
 #[macro_use]
 extern crate num_derive;
+#[cfg(feature = "libwz")]
 #[allow(unused_imports)]
 use libz_sys::*;
+#[cfg(feature = "libwz")]
 mod c_wz;
+pub mod error;
+#[cfg(feature = "image")]
+mod export;
+pub mod frame;
+pub mod from_node;
 pub mod node;
+#[cfg(all(feature = "rayon", any(feature = "libwz", feature = "pure")))]
+mod par;
+pub mod path;
+pub mod pixel;
+#[cfg(feature = "pure")]
+#[cfg_attr(docsrs, doc(cfg(feature = "pure")))]
+pub mod pure;
+pub mod query;
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub mod ser;
+pub mod sound;
+pub mod value;
+pub mod walk;
+#[cfg(feature = "libwz")]
+#[cfg_attr(docsrs, doc(cfg(feature = "libwz")))]
 pub mod wz;
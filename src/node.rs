@@ -1,60 +1,326 @@
-use anyhow::Result;
+// num-derive 0.3 puts the impls of `Dtype` in an anonymous const, which newer compilers lint
+#![allow(unknown_lints, non_local_definitions)]
 
+use crate::{
+    error::{Result, WzError},
+    query::Select,
+    sound::WaveFormat,
+    value::WzValue,
+    walk::Walk,
+};
+use std::borrow::Cow;
+
+/// Errors of every method are [`WzError`](crate::error::WzError)s carrying the path of the node.
 pub trait MapleNode {
     /// The type of the elements being indexed.
     type Item;
 
-    /// Get child WzNode with given path.
-    /// Return [`None`] when path not exists or error occurred.
-    fn child<S: AsRef<str>>(&self, path: S) -> Option<Box<Self::Item>>;
+    /// Get child WzNode with given path, in which `..` is the parent and `.` the node itself.
+    /// Return [`WzError::NotFound`](crate::error::WzError::NotFound) when path not exists.
+    fn child<S: AsRef<str>>(&self, path: S) -> Result<Box<Self::Item>>;
 
     /// Get the i th child WzNode with given index i.
-    /// Return [`None`] when no child or the WzNode is not [`Dtype::ARY`] or [`Dtype::IMG`].
-    fn child_at(&self, i: u32) -> Option<Box<Self::Item>>;
+    /// Return [`WzError::IndexOutOfRange`](crate::error::WzError::IndexOutOfRange) when no such child,
+    /// e.g. the WzNode is not [`Dtype::ARY`] or [`Dtype::IMG`].
+    fn child_at(&self, i: u32) -> Result<Box<Self::Item>>;
 
     /// Get the number of children of node
     fn len(&self) -> u32;
 
+    /// Whether node has no children
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the canonical path of node from the root of its file, see [`WzPath`](crate::path::WzPath).
+    fn path(&self) -> &str;
+
+    /// Get the parent of node.
+    /// Return [`WzError::NotFound`](crate::error::WzError::NotFound) for the root.
+    fn parent(&self) -> Result<Box<Self::Item>>;
+
+    /// Get the root node of the file of node.
+    fn root(&self) -> Result<Box<Self::Item>>;
+
     /// get [`Dtype`] of node
-    fn dtype(&self) -> Result<Option<Dtype>>;
+    fn dtype(&self) -> Result<Dtype>;
 
     /// Get the i32 value of node with type [`Dtype::I16`] or [`Dtype::I32`]
-    fn int32(&self) -> Result<Option<i32>>;
+    fn int32(&self) -> Result<i32>;
 
     /// Get the i64 value of node with type [`Dtype::I64`]
-    fn int64(&self) -> Result<Option<i64>>;
+    fn int64(&self) -> Result<i64>;
 
     /// Get the f32 value of node with type [`Dtype::F32`]
-    fn float32(&self) -> Result<Option<f32>>;
+    fn float32(&self) -> Result<f32>;
 
     /// Get the f64 value of node with type [`Dtype::F64`]
-    fn float64(&self) -> Result<Option<f64>>;
+    fn float64(&self) -> Result<f64>;
+
+    /// Get the value of node with type [`Dtype::I16`] or [`Dtype::I32`],
+    /// or a [`Dtype::STR`] holding an i32 number.
+    fn as_i32(&self) -> Result<i32> {
+        match self.dtype()? {
+            Dtype::I16 | Dtype::I32 => self.int32(),
+            Dtype::STR => parse_str(self, Dtype::I32),
+            _ => Err(WzError::mismatch(self, Dtype::I32)),
+        }
+    }
+
+    /// Get the value of node with type [`Dtype::I16`], [`Dtype::I32`] or [`Dtype::I64`],
+    /// or a [`Dtype::STR`] holding an i64 number.
+    fn as_i64(&self) -> Result<i64> {
+        match self.dtype()? {
+            Dtype::I16 | Dtype::I32 => Ok(self.int32()? as i64),
+            Dtype::I64 => self.int64(),
+            Dtype::STR => parse_str(self, Dtype::I64),
+            _ => Err(WzError::mismatch(self, Dtype::I64)),
+        }
+    }
+
+    /// Get the value of node with type [`Dtype::F32`], or a [`Dtype::STR`] holding a number.
+    fn as_f32(&self) -> Result<f32> {
+        match self.dtype()? {
+            Dtype::F32 => self.float32(),
+            Dtype::STR => parse_str(self, Dtype::F32),
+            _ => Err(WzError::mismatch(self, Dtype::F32)),
+        }
+    }
+
+    /// Get the value of node with type [`Dtype::F32`], [`Dtype::F64`], [`Dtype::I16`] or [`Dtype::I32`],
+    /// or a [`Dtype::STR`] holding a number.
+    fn as_f64(&self) -> Result<f64> {
+        match self.dtype()? {
+            Dtype::I16 | Dtype::I32 => Ok(self.int32()? as f64),
+            Dtype::F32 => Ok(self.float32()? as f64),
+            Dtype::F64 => self.float64(),
+            Dtype::STR => parse_str(self, Dtype::F64),
+            _ => Err(WzError::mismatch(self, Dtype::F64)),
+        }
+    }
 
     /// Get the str of node with type [`Dtype::STR`]
-    fn str(&self) -> Result<Option<&'static str>>;
+    fn str(&self) -> Result<&str>;
+
+    /// Get an owned copy of the str of node with type [`Dtype::STR`], which may outlive the file.
+    fn str_owned(&self) -> Result<String> {
+        Ok(self.str()?.to_owned())
+    }
+
+    /// Get the link of node with type [`Dtype::UOL`], relative to the node containing it.
+    fn uol(&self) -> Result<&str>;
+
+    /// Follow the link of node with type [`Dtype::UOL`] (and any UOL it points at).
+    /// Fail when the node is not a UOL, the link is broken or the links form a cycle.
+    fn resolve(&self) -> Result<Box<Self::Item>>;
 
     /// Get the name of node
-    fn name(&self) -> Result<Option<&'static str>>;
+    fn name(&self) -> Result<&str>;
+
+    /// Get an owned copy of the name of node, which may outlive the file.
+    fn name_owned(&self) -> Result<String> {
+        Ok(self.name()?.to_owned())
+    }
 
     /// Get the number of children of convex of node with type [`Dtype::VEX`].
     fn vex_len(&self) -> Result<u32>;
 
+    /// Get the i th point of convex of node with type [`Dtype::VEX`].
+    /// An `i` of at least [`vex_len`](MapleNode::vex_len) is a [`WzError::IndexOutOfRange`](crate::error::WzError::IndexOutOfRange).
+    fn vex_at(&self, i: u32) -> Result<glam::IVec2>;
+
+    /// Get all points of convex of node with type [`Dtype::VEX`] as a polygon.
+    fn vex(&self) -> Result<Vec<glam::IVec2>>;
+
     /// Get the vector of node with type [`Dtype::VEC`]
-    fn vec(&self) -> Result<Option<glam::Vec2>>;
+    fn vec(&self) -> Result<glam::Vec2>;
 
     /// Get the img of node with type[`Dtype::IMG`]
-    // fn img(&self) -> Result<Option<DynamicImage>>;
-    fn img<'a>(&self) -> Result<Option<ImageBuffer<'a>>>;
+    /// The pixels are borrowed from the node, see [`ImageBuffer::into_owned`] to keep them longer.
+    fn img(&self) -> Result<ImageBuffer<'_>>;
+
+    /// Get the size and stored format of the img of node with type [`Dtype::IMG`], without its pixels.
+    /// Backends which keep them apart, such as `pure`, do not decode the canvas.
+    fn img_info(&self) -> Result<ImageInfo> {
+        Ok(self.img()?.info())
+    }
+
+    /// Get the img of node with type [`Dtype::IMG`] as an RGBA image,
+    /// which converts into an [`image::DynamicImage`] with `into()`.
+    #[cfg(feature = "image")]
+    fn rgba_image(&self) -> Result<image::RgbaImage> {
+        self.img()?.to_rgba_image().map_err(|e| e.with_path(self.path()))
+    }
+
+    /// Get the audio of node with type [`Dtype::AO`]
+    /// The data is borrowed from the node, see [`AudioBuffer::into_owned`] to keep it longer.
+    fn audio(&self) -> Result<AudioBuffer<'_>>;
+
+    /// Walk the descendants of node depth first, each node before its children.
+    fn walk_dfs<'a>(&self) -> Walk<'a, Self::Item>
+    where
+        Self::Item: MapleNode<Item = Self::Item>,
+    {
+        Walk::new(self, false)
+    }
+
+    /// Walk the descendants of node breadth first, level by level.
+    fn walk_bfs<'a>(&self) -> Walk<'a, Self::Item>
+    where
+        Self::Item: MapleNode<Item = Self::Item>,
+    {
+        Walk::new(self, true)
+    }
+
+    /// Select the descendants of node matching a query such as `*/info[level>50]/maxHP`,
+    /// see [`Select`] for the syntax. Fails with [`WzError::InvalidQuery`] when the query does not parse.
+    fn select(&self, query: &str) -> Result<Select<Self::Item>>
+    where
+        Self::Item: MapleNode<Item = Self::Item>,
+    {
+        Select::new(self, query)
+    }
+
+    /// Get an owned copy of the payload of node, matching its [`Dtype`].
+    /// Pixels and audio data are copied, children are not visited.
+    fn value(&self) -> Result<WzValue> {
+        Ok(match self.dtype()? {
+            Dtype::NIL | Dtype::UNK => WzValue::Nil,
+            Dtype::I16 | Dtype::I32 => WzValue::Int(self.int32()?),
+            Dtype::I64 => WzValue::Long(self.int64()?),
+            Dtype::F32 => WzValue::Float(self.float32()?),
+            Dtype::F64 => WzValue::Double(self.float64()?),
+            Dtype::VEC => WzValue::Vec(self.vec()?),
+            Dtype::ARY => WzValue::Property,
+            Dtype::IMG => WzValue::Canvas(self.img()?.into_owned()),
+            Dtype::VEX => WzValue::Convex(self.vex()?),
+            Dtype::AO => WzValue::Audio(self.audio()?.into_owned()),
+            Dtype::UOL => WzValue::Uol(self.uol()?.to_owned()),
+            Dtype::STR => WzValue::Str(self.str_owned()?),
+        })
+    }
+}
+
+/// Parse the str of node as a number, reported as `expected` type on failure.
+fn parse_str<N: MapleNode + ?Sized, T: std::str::FromStr>(node: &N, expected: Dtype) -> Result<T> {
+    node.str()?.trim().parse().map_err(|_| WzError::mismatch(node, expected))
+}
+
+/// Follow the chain of UOLs starting at node until a non-UOL node,
+/// opening each target with `open_absolute` from the root of the file.
+/// Fail when a link is broken or the chain is cyclic.
+#[cfg(any(feature = "libwz", feature = "pure"))]
+pub(crate) fn follow_links<N: MapleNode>(
+    node: Box<N>,
+    open_absolute: impl Fn(&N, &crate::path::WzPath) -> Result<Box<N>>,
+) -> Result<Box<N>> {
+    let mut visited = std::collections::HashSet::new();
+    let mut current = node;
+    while current.dtype()? == Dtype::UOL {
+        let path = current.path().to_owned();
+        if !visited.insert(path.clone()) {
+            return Err(WzError::CyclicLink { path });
+        }
+        let link = current.uol()?;
+        let broken = || WzError::BrokenLink { path: path.clone(), link: link.to_owned() };
+        let target = resolve_link(&path, link).ok_or_else(broken)?;
+        current = open_absolute(&current, &target).map_err(|_| broken())?;
+    }
+    Ok(current)
+}
+
+/// Resolve a UOL link relative to the directory containing the UOL node at `path`.
+/// Return [`None`] when the link escapes the root.
+#[cfg(any(feature = "libwz", feature = "pure"))]
+fn resolve_link(path: &str, link: &str) -> Option<crate::path::WzPath> {
+    crate::path::WzPath::new(path)?.parent()?.join(link)
+}
+
+/// Format node as `WzNode Path[..] Type[..] Value[..]`, shared by the `Debug` impls of every backend.
+#[cfg(any(feature = "libwz", feature = "pure"))]
+pub(crate) fn fmt_node<N: MapleNode + ?Sized>(node: &N, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let path = node.path();
+    let mut dtype = "Error".to_string();
+    if let Ok(t) = node.dtype() {
+        dtype = t.to_str().to_owned();
+    }
+
+    // canvases and sounds print their metadata only, their payload is neither decoded nor copied
+    let val = match node.dtype() {
+        Ok(Dtype::NIL) | Ok(Dtype::UNK) | Err(_) => String::new(),
+        Ok(Dtype::ARY) => format!("child num={}", node.len()),
+        Ok(Dtype::IMG) => match node.img_info() {
+            Ok(info) => format!("dim:{}x{},child num={}", info.width, info.height, node.len()),
+            Err(_) => format!("child num={}", node.len()),
+        },
+        Ok(Dtype::AO) => {
+            node.audio().map(|a| format!("size:{},ms:{},format:{}", a.size, a.ms, a.format)).unwrap_or_default()
+        }
+        Ok(_) => node.value().map(|v| v.to_string()).unwrap_or_default(),
+    };
+
+    let _ = write!(f, "WzNode Path[{path}] Type[{dtype}] Value[{val}]", path = path, dtype = dtype, val = val);
+    Ok(())
 }
 
+/// The pixels of a canvas, see [`ImageBuffer::to_rgba8`] to convert them.
+#[derive(Clone, Debug, PartialEq)]
 pub struct ImageBuffer<'a> {
-    pub data: &'a mut [u8],
+    /// BGRA8888 pixels of `width` x `height`, decoded and upscaled by [`MapleNode::img`].
+    pub data: Cow<'a, [u8]>,
     pub width: u32,
     pub height: u32,
+    /// The pixel format the canvas is stored in, see [`PixelFormat`](crate::pixel::PixelFormat).
     pub depth: u16,
+    /// The log2 of the size of each stored pixel, which `data` is already upscaled from.
     pub scale: u8,
 }
 
+impl ImageBuffer<'_> {
+    /// Copy the pixels out of the file, so that the buffer may outlive it.
+    pub fn into_owned(self) -> ImageBuffer<'static> {
+        let ImageBuffer { data, width, height, depth, scale } = self;
+        ImageBuffer { data: Cow::Owned(data.into_owned()), width, height, depth, scale }
+    }
+
+    /// Get the size and stored format of the pixels.
+    pub fn info(&self) -> ImageInfo {
+        ImageInfo { width: self.width, height: self.height, depth: self.depth, scale: self.scale }
+    }
+}
+
+/// The size and stored format of a canvas, see [`MapleNode::img_info`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub width: u32,
+    pub height: u32,
+    /// The pixel format the canvas is stored in, see [`PixelFormat`](crate::pixel::PixelFormat).
+    pub depth: u16,
+    /// The log2 of the size of each stored pixel.
+    pub scale: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioBuffer<'a> {
+    /// The sound as stored after its header, e.g. MP3 frames, see [`AudioBuffer::codec`].
+    pub data: Cow<'a, [u8]>,
+    pub size: u32,
+    pub ms: u32,
+    /// The `wFormatTag` of the header, 0 when the sound has none.
+    pub format: u16,
+    /// The header, [`None`] when the sound has none. libwz only exposes it for sounds it hands out as a WAV file,
+    /// so [`AudioBuffer::to_wav`] may fail for other PCM sounds there, which the `pure` backend converts.
+    pub wave_format: Option<WaveFormat>,
+}
+
+impl AudioBuffer<'_> {
+    /// Copy the data out of the file, so that the buffer may outlive it.
+    pub fn into_owned(self) -> AudioBuffer<'static> {
+        let AudioBuffer { data, size, ms, format, wave_format } = self;
+        AudioBuffer { data: Cow::Owned(data.into_owned()), size, ms, format, wave_format }
+    }
+}
+
 #[repr(u16)]
 #[derive(PartialEq, Eq, Clone, Copy, Debug, FromPrimitive, ToPrimitive)]
 pub enum Dtype {
@@ -81,7 +347,7 @@ pub enum Dtype {
     IMG = 9,
     /// a node with convex (multiple pairs of x: i32 and y: i32)
     VEX = 10,
-    /// a node with audio (data: *u8, size: u32, ms: u32, format: u16)
+    /// a node with audio (data: *u8, size: u32, ms: u32, format: u16), see [`WaveFormat`] for the header
     AO = 11,
     /// UOL
     UOL = 12,
@@ -109,3 +375,288 @@ impl Dtype {
         }
     }
 }
+
+impl<T: MapleNode> MapleNode for &mut T {
+    type Item = T::Item;
+
+    fn child<S: AsRef<str>>(&self, path: S) -> Result<Box<Self::Item>> {
+        (**self).child(path)
+    }
+
+    fn child_at(&self, i: u32) -> Result<Box<Self::Item>> {
+        (**self).child_at(i)
+    }
+
+    fn len(&self) -> u32 {
+        (**self).len()
+    }
+
+    fn path(&self) -> &str {
+        (**self).path()
+    }
+
+    fn parent(&self) -> Result<Box<Self::Item>> {
+        (**self).parent()
+    }
+
+    fn root(&self) -> Result<Box<Self::Item>> {
+        (**self).root()
+    }
+
+    fn dtype(&self) -> Result<Dtype> {
+        (**self).dtype()
+    }
+
+    fn int32(&self) -> Result<i32> {
+        (**self).int32()
+    }
+
+    fn int64(&self) -> Result<i64> {
+        (**self).int64()
+    }
+
+    fn float32(&self) -> Result<f32> {
+        (**self).float32()
+    }
+
+    fn float64(&self) -> Result<f64> {
+        (**self).float64()
+    }
+
+    fn str(&self) -> Result<&str> {
+        (**self).str()
+    }
+
+    fn uol(&self) -> Result<&str> {
+        (**self).uol()
+    }
+
+    fn resolve(&self) -> Result<Box<Self::Item>> {
+        (**self).resolve()
+    }
+
+    fn name(&self) -> Result<&str> {
+        (**self).name()
+    }
+
+    fn vex_len(&self) -> Result<u32> {
+        (**self).vex_len()
+    }
+
+    fn vex_at(&self, i: u32) -> Result<glam::IVec2> {
+        (**self).vex_at(i)
+    }
+
+    fn vex(&self) -> Result<Vec<glam::IVec2>> {
+        (**self).vex()
+    }
+
+    fn vec(&self) -> Result<glam::Vec2> {
+        (**self).vec()
+    }
+
+    fn img(&self) -> Result<ImageBuffer<'_>> {
+        (**self).img()
+    }
+
+    fn img_info(&self) -> Result<ImageInfo> {
+        (**self).img_info()
+    }
+
+    fn audio(&self) -> Result<AudioBuffer<'_>> {
+        (**self).audio()
+    }
+}
+
+/// Chaining on the result of [`MapleNode::child`], so that `root.child("a").child("b").int32()`
+/// reports the first error met on the way.
+impl<T: MapleNode> MapleNode for Result<Box<T>> {
+    type Item = T::Item;
+
+    fn child<S: AsRef<str>>(&self, path: S) -> Result<Box<Self::Item>> {
+        self.as_ref().map_err(Clone::clone)?.child(path)
+    }
+
+    fn child_at(&self, i: u32) -> Result<Box<Self::Item>> {
+        self.as_ref().map_err(Clone::clone)?.child_at(i)
+    }
+
+    fn len(&self) -> u32 {
+        self.as_ref().map(|node| node.len()).unwrap_or(0)
+    }
+
+    fn path(&self) -> &str {
+        self.as_ref().map(|node| node.path()).unwrap_or("")
+    }
+
+    fn parent(&self) -> Result<Box<Self::Item>> {
+        self.as_ref().map_err(Clone::clone)?.parent()
+    }
+
+    fn root(&self) -> Result<Box<Self::Item>> {
+        self.as_ref().map_err(Clone::clone)?.root()
+    }
+
+    fn dtype(&self) -> Result<Dtype> {
+        self.as_ref().map_err(Clone::clone)?.dtype()
+    }
+
+    fn int32(&self) -> Result<i32> {
+        self.as_ref().map_err(Clone::clone)?.int32()
+    }
+
+    fn int64(&self) -> Result<i64> {
+        self.as_ref().map_err(Clone::clone)?.int64()
+    }
+
+    fn float32(&self) -> Result<f32> {
+        self.as_ref().map_err(Clone::clone)?.float32()
+    }
+
+    fn float64(&self) -> Result<f64> {
+        self.as_ref().map_err(Clone::clone)?.float64()
+    }
+
+    fn str(&self) -> Result<&str> {
+        self.as_ref().map_err(Clone::clone)?.str()
+    }
+
+    fn uol(&self) -> Result<&str> {
+        self.as_ref().map_err(Clone::clone)?.uol()
+    }
+
+    fn resolve(&self) -> Result<Box<Self::Item>> {
+        self.as_ref().map_err(Clone::clone)?.resolve()
+    }
+
+    fn name(&self) -> Result<&str> {
+        self.as_ref().map_err(Clone::clone)?.name()
+    }
+
+    fn vex_len(&self) -> Result<u32> {
+        self.as_ref().map_err(Clone::clone)?.vex_len()
+    }
+
+    fn vex_at(&self, i: u32) -> Result<glam::IVec2> {
+        self.as_ref().map_err(Clone::clone)?.vex_at(i)
+    }
+
+    fn vex(&self) -> Result<Vec<glam::IVec2>> {
+        self.as_ref().map_err(Clone::clone)?.vex()
+    }
+
+    fn vec(&self) -> Result<glam::Vec2> {
+        self.as_ref().map_err(Clone::clone)?.vec()
+    }
+
+    fn img(&self) -> Result<ImageBuffer<'_>> {
+        self.as_ref().map_err(Clone::clone)?.img()
+    }
+
+    fn img_info(&self) -> Result<ImageInfo> {
+        self.as_ref().map_err(Clone::clone)?.img_info()
+    }
+
+    fn audio(&self) -> Result<AudioBuffer<'_>> {
+        self.as_ref().map_err(Clone::clone)?.audio()
+    }
+}
+
+/// Chaining on an optional node, as [`MapleNode::child`] returned before it reported errors,
+/// e.g. `root.child("a").ok().child("b")`. A missing node fails with
+/// [`WzError::NotFound`](crate::error::WzError::NotFound) with an empty path.
+impl<T: MapleNode> MapleNode for Option<Box<T>> {
+    type Item = T::Item;
+
+    fn child<S: AsRef<str>>(&self, path: S) -> Result<Box<Self::Item>> {
+        missing(self)?.child(path)
+    }
+
+    fn child_at(&self, i: u32) -> Result<Box<Self::Item>> {
+        missing(self)?.child_at(i)
+    }
+
+    fn len(&self) -> u32 {
+        self.as_ref().map(|node| node.len()).unwrap_or(0)
+    }
+
+    fn path(&self) -> &str {
+        self.as_ref().map(|node| node.path()).unwrap_or("")
+    }
+
+    fn parent(&self) -> Result<Box<Self::Item>> {
+        missing(self)?.parent()
+    }
+
+    fn root(&self) -> Result<Box<Self::Item>> {
+        missing(self)?.root()
+    }
+
+    fn dtype(&self) -> Result<Dtype> {
+        missing(self)?.dtype()
+    }
+
+    fn int32(&self) -> Result<i32> {
+        missing(self)?.int32()
+    }
+
+    fn int64(&self) -> Result<i64> {
+        missing(self)?.int64()
+    }
+
+    fn float32(&self) -> Result<f32> {
+        missing(self)?.float32()
+    }
+
+    fn float64(&self) -> Result<f64> {
+        missing(self)?.float64()
+    }
+
+    fn str(&self) -> Result<&str> {
+        missing(self)?.str()
+    }
+
+    fn uol(&self) -> Result<&str> {
+        missing(self)?.uol()
+    }
+
+    fn resolve(&self) -> Result<Box<Self::Item>> {
+        missing(self)?.resolve()
+    }
+
+    fn name(&self) -> Result<&str> {
+        missing(self)?.name()
+    }
+
+    fn vex_len(&self) -> Result<u32> {
+        missing(self)?.vex_len()
+    }
+
+    fn vex_at(&self, i: u32) -> Result<glam::IVec2> {
+        missing(self)?.vex_at(i)
+    }
+
+    fn vex(&self) -> Result<Vec<glam::IVec2>> {
+        missing(self)?.vex()
+    }
+
+    fn vec(&self) -> Result<glam::Vec2> {
+        missing(self)?.vec()
+    }
+
+    fn img(&self) -> Result<ImageBuffer<'_>> {
+        missing(self)?.img()
+    }
+
+    fn img_info(&self) -> Result<ImageInfo> {
+        missing(self)?.img_info()
+    }
+
+    fn audio(&self) -> Result<AudioBuffer<'_>> {
+        missing(self)?.audio()
+    }
+}
+
+fn missing<T>(node: &Option<Box<T>>) -> Result<&T> {
+    node.as_deref().ok_or_else(|| WzError::NotFound { path: String::new() })
+}
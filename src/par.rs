@@ -0,0 +1,171 @@
+use crate::{error::Result, node::MapleNode, path::WzPath};
+use rayon::prelude::*;
+
+/// Results of visiting nodes paired with their paths.
+type Walked<T> = Vec<Result<(WzPath, T)>>;
+
+/// Visit the directories and `.img`s under root in order, collecting what `visit` returns for them,
+/// and the paths of the `.img` subtrees met to be walked in parallel.
+fn split<N, T, F>(root: &N, visit: &F) -> (Walked<T>, Vec<WzPath>)
+where
+    N: MapleNode<Item = N>,
+    F: Fn(&N) -> Result<T>,
+{
+    let (mut dirs, mut images) = (vec![], vec![]);
+    let mut pending = children_rev(root);
+    while let Some(node) = pending.pop() {
+        let node = match node {
+            Ok(node) => node,
+            Err(e) => {
+                dirs.push(Err(e));
+                continue;
+            }
+        };
+        dirs.push(visit(&node).map(|visited| (path_of(&*node), visited)));
+        if node.name().is_ok_and(|name| name.ends_with(".img")) {
+            images.push(path_of(&*node));
+        } else {
+            pending.extend(children_rev(&*node));
+        }
+    }
+    (dirs, images)
+}
+
+/// Get the path of node, which being canonical never escapes the root.
+fn path_of<N: MapleNode>(node: &N) -> WzPath {
+    WzPath::new(node.path()).unwrap_or_else(WzPath::root)
+}
+
+/// Open the children of node, last first, to be popped from a stack in order.
+fn children_rev<N: MapleNode<Item = N>>(node: &N) -> Vec<Result<Box<N>>> {
+    (0..node.len()).rev().map(|i| node.child_at(i)).collect()
+}
+
+/// Visit every node under node, depth first.
+fn walk<N, T, F>(node: &N, visit: &F, out: &mut Walked<T>)
+where
+    N: MapleNode<Item = N>,
+    F: Fn(&N) -> Result<T>,
+{
+    for i in 0..node.len() {
+        match node.child_at(i) {
+            Ok(child) => {
+                out.push(visit(&child).map(|visited| (path_of(&*child), visited)));
+                walk(&*child, visit, out);
+            }
+            Err(e) => out.push(Err(e)),
+        }
+    }
+}
+
+/// Visit the nodes of the `.img` at path under root, then unload it so that memory stays bounded.
+fn walk_image<N, T, F>(root: &N, path: &WzPath, visit: &F, unload: impl Fn(&N)) -> Walked<T>
+where
+    N: MapleNode<Item = N>,
+    F: Fn(&N) -> Result<T>,
+{
+    let mut out = vec![];
+    match root.child(path.as_str().trim_start_matches('/')) {
+        Ok(image) => {
+            walk(&*image, visit, &mut out);
+            unload(&image);
+        }
+        Err(e) => out.push(Err(e)),
+    }
+    out
+}
+
+#[cfg(feature = "libwz")]
+impl crate::wz::SharedWzFile {
+    /// Visit every node of the file, yielding its path and what `visit` returns for it,
+    /// e.g. `|node| node.value()` for an owned copy of every value.
+    /// Payloads are only read when `visit` asks for them, e.g. with [`MapleNode::img`].
+    ///
+    /// Directories and `.img`s are visited first on this handle, then the `.img` subtrees are walked
+    /// in parallel, each unloaded once walked. Jobs take handles of the file from a pool,
+    /// opening one only when every handle is busy, so that threads neither wait on each other nor reopen the file.
+    /// Collected items list the directories and `.img`s depth first, then the nodes of each image in turn.
+    pub fn par_walk<'a, T, F>(&'a self, visit: F) -> Result<impl ParallelIterator<Item = Result<(WzPath, T)>> + 'a>
+    where
+        T: Send + 'a,
+        F: Fn(&crate::wz::WzNode<'_>) -> Result<T> + Send + Sync + 'a,
+    {
+        let (dirs, images) = self.with_root(|root| split(root, &visit))?;
+        let pool = Pool { path: self.path().to_owned(), handles: Default::default() };
+        let walked = images
+            .into_par_iter()
+            .map(move |path| {
+                pool.with(|file| file.with_root(|root| walk_image(root, &path, &visit, |image| image.unload())))?
+            })
+            .flat_map_iter(|walked: Result<Walked<T>>| walked.unwrap_or_else(|e| vec![Err(e)]));
+        Ok(dirs.into_par_iter().chain(walked))
+    }
+}
+
+/// Handles of a file shared by the jobs of [`crate::wz::SharedWzFile::par_walk`],
+/// each used by one job at a time.
+#[cfg(feature = "libwz")]
+struct Pool {
+    path: String,
+    handles: std::sync::Mutex<Vec<crate::wz::SharedWzFile>>,
+}
+
+#[cfg(feature = "libwz")]
+impl Pool {
+    /// Run `f` on an idle handle, opening a new one when there is none.
+    fn with<R>(&self, f: impl FnOnce(&crate::wz::SharedWzFile) -> R) -> Result<R> {
+        let idle = self.handles.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let file = match idle {
+            Some(file) => file,
+            None => crate::wz::SharedWzFile::open(&self.path)?,
+        };
+        let result = f(&file);
+        self.handles.lock().unwrap_or_else(|e| e.into_inner()).push(file);
+        Ok(result)
+    }
+}
+
+#[cfg(feature = "pure")]
+impl crate::pure::WzFile {
+    /// Visit every node of the file, yielding its path and what `visit` returns for it,
+    /// e.g. `|node| node.value()` for an owned copy of every value.
+    /// Payloads are only read when `visit` asks for them, e.g. with [`MapleNode::img`].
+    ///
+    /// Directories and `.img`s are visited first, then the `.img` subtrees are walked in parallel
+    /// on this shared file. Each image is unloaded once walked, so that memory stays bounded.
+    /// Collected items list the directories and `.img`s depth first, then the nodes of each image in turn.
+    pub fn par_walk<'a, T, F>(&'a self, visit: F) -> Result<impl ParallelIterator<Item = Result<(WzPath, T)>> + 'a>
+    where
+        T: Send + 'a,
+        F: Fn(&crate::pure::WzNode<'_>) -> Result<T> + Send + Sync + 'a,
+    {
+        let (dirs, images) = split(&*self.open_root()?, &visit);
+        let walked = images
+            .into_par_iter()
+            .map(move |path| Ok(walk_image(&*self.open_root()?, &path, &visit, |image| image.unload())))
+            .flat_map_iter(|walked: Result<Walked<T>>| walked.unwrap_or_else(|e| vec![Err(e)]));
+        Ok(dirs.into_par_iter().chain(walked))
+    }
+}
+
+#[cfg(all(test, feature = "pure"))]
+mod tests {
+    use crate::{
+        node::{Dtype, MapleNode},
+        path::WzPath,
+        pure::fixture,
+    };
+    use rayon::prelude::*;
+
+    #[test]
+    fn par_walk() {
+        let file = fixture::open();
+        let walked: Vec<_> = file.par_walk(|node| node.dtype()).unwrap().collect::<crate::error::Result<_>>().unwrap();
+        let dtype = |path: &str| walked.iter().find(|(p, _)| p.as_str() == path).map(|(_, dtype)| *dtype);
+        assert_eq!(walked[0].0, WzPath::new("Mob").unwrap());
+        assert_eq!(dtype("/Test.img/int"), Some(Dtype::I32));
+        assert_eq!(dtype("/Test.img/canvas/origin"), Some(Dtype::VEC));
+        assert_eq!(dtype("/Mob/Mob.img/hp"), Some(Dtype::I32));
+        assert_eq!(file.memory_usage(), 0);
+    }
+}
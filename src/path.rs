@@ -0,0 +1,124 @@
+use std::fmt::{Display, Formatter};
+
+/// The canonical path of a node from the root of its file, e.g. `/Mob/100100.img/info`.
+///
+/// It always starts with `/`, which alone is the root, and has no empty, `.` or `..` segments.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WzPath(String);
+
+impl WzPath {
+    /// Get the path of the root node, `/`.
+    pub fn root() -> Self {
+        WzPath("/".to_owned())
+    }
+
+    /// Normalize path from the root, see [`WzPath::join`].
+    /// Return [`None`] when a `..` escapes the root.
+    pub fn new<S: AsRef<str>>(path: S) -> Option<Self> {
+        WzPath::root().join(path)
+    }
+
+    /// Resolve a relative path against this one, where `..` is the parent and `.` the node itself.
+    /// Empty segments are skipped, so a leading `/` is relative too.
+    /// Return [`None`] when a `..` escapes the root.
+    pub fn join<S: AsRef<str>>(&self, relative: S) -> Option<Self> {
+        let mut path = self.clone();
+        for segment in relative.as_ref().split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => path = path.parent()?,
+                name => path.push(name),
+            }
+        }
+        Some(path)
+    }
+
+    /// Get the path of the parent node, [`None`] for the root.
+    pub fn parent(&self) -> Option<Self> {
+        match self.0.rfind('/')? {
+            _ if self.is_root() => None,
+            0 => Some(WzPath::root()),
+            end => Some(WzPath(self.0[..end].to_owned())),
+        }
+    }
+
+    /// Get the name of the node, the last segment, [`None`] for the root.
+    pub fn name(&self) -> Option<&str> {
+        self.segments().next_back()
+    }
+
+    /// Iterate the names of the nodes from the root down to this one, excluding the root.
+    pub fn segments(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.0.split('/').filter(|s| !s.is_empty())
+    }
+
+    /// Get the number of segments, 0 for the root.
+    pub fn depth(&self) -> usize {
+        self.segments().count()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0 == "/"
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Append the name of a child as is, which must be a valid name, see [`WzPath::child`].
+    fn push(&mut self, name: &str) {
+        if !self.is_root() {
+            self.0.push('/');
+        }
+        self.0.push_str(name);
+    }
+
+    /// Get the path of a child with given name.
+    /// Return [`None`] when the name is empty, `.`, `..` or contains `/`, which no segment of a path can be.
+    #[cfg(any(feature = "libwz", feature = "pure", test))]
+    pub(crate) fn child(&self, name: &str) -> Option<Self> {
+        if matches!(name, "" | "." | "..") || name.contains('/') {
+            return None;
+        }
+        let mut path = self.clone();
+        path.push(name);
+        Some(path)
+    }
+}
+
+impl Display for WzPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for WzPath {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join() {
+        let path = WzPath::new("Mob/100100.img").unwrap();
+        assert_eq!(path.join("info/../info/./level").unwrap().as_str(), "/Mob/100100.img/info/level");
+        assert_eq!(path.join("../..").unwrap(), WzPath::root());
+        assert_eq!(path.join("../../.."), None);
+        assert_eq!(path.parent().unwrap().as_str(), "/Mob");
+        assert_eq!(path.name(), Some("100100.img"));
+        assert_eq!(path.depth(), 2);
+    }
+
+    #[test]
+    fn child() {
+        assert_eq!(WzPath::root().child("Mob").unwrap().as_str(), "/Mob");
+        assert_eq!(WzPath::new("Mob").unwrap().child("1.img").unwrap().as_str(), "/Mob/1.img");
+        for name in ["", ".", "..", "a/b", "/"] {
+            assert_eq!(WzPath::root().child(name), None, "{}", name);
+        }
+    }
+}
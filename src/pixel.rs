@@ -0,0 +1,392 @@
+use crate::{
+    error::{Result, WzError},
+    node::{ImageBuffer, ImageInfo},
+};
+#[cfg(feature = "image")]
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use std::convert::TryInto;
+#[cfg(feature = "image")]
+use std::path::Path;
+
+/// The pixel format a canvas is stored in, read from its [`ImageBuffer::depth`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 16 bits per pixel, 4 bits for each of blue, green, red and alpha from the low bits up.
+    Argb4444,
+    /// 32 bits per pixel, a byte for each of blue, green, red and alpha: BGRA8888 in memory.
+    Argb8888,
+    /// 16 bits per pixel, 5 bits for each of blue, green and red and a bit of alpha from the low bits up.
+    Argb1555,
+    /// 16 bits per pixel, 5 bits of blue, 6 of green and 5 of red from the low bits up, opaque.
+    Rgb565,
+    /// Blocks of 4 x 4 pixels in 16 bytes: 4 bits of alpha per pixel, then two RGB565 colors and 2 bit indices.
+    Dxt3,
+    /// Blocks of 4 x 4 pixels in 16 bytes: two alphas and 3 bit indices, then two RGB565 colors and 2 bit indices.
+    Dxt5,
+}
+
+impl PixelFormat {
+    /// Get the format of a canvas depth, with the log2 scale some depths fold into it,
+    /// e.g. 3, the monochrome thumbnails, are ARGB4444 in blocks of 4 x 4 pixels.
+    /// Return [`None`] for an unknown depth.
+    pub fn from_depth(depth: u16) -> Option<(PixelFormat, u8)> {
+        Some(match depth {
+            1 => (PixelFormat::Argb4444, 0),
+            2 => (PixelFormat::Argb8888, 0),
+            3 => (PixelFormat::Argb4444, 2),
+            257 => (PixelFormat::Argb1555, 0),
+            513 => (PixelFormat::Rgb565, 0),
+            517 => (PixelFormat::Rgb565, 4),
+            1026 => (PixelFormat::Dxt3, 0),
+            2050 => (PixelFormat::Dxt5, 0),
+            _ => return None,
+        })
+    }
+
+    /// Get the bytes taken by `w` x `h` pixels in format, [`None`] when they overflow `usize`.
+    pub fn stored_len(self, w: usize, h: usize) -> Option<usize> {
+        match self {
+            PixelFormat::Argb4444 | PixelFormat::Argb1555 | PixelFormat::Rgb565 => w.checked_mul(h)?.checked_mul(2),
+            PixelFormat::Argb8888 => w.checked_mul(h)?.checked_mul(4),
+            PixelFormat::Dxt3 | PixelFormat::Dxt5 => w.div_ceil(4).checked_mul(h.div_ceil(4))?.checked_mul(16),
+        }
+    }
+}
+
+/// The layout of the stored pixels of a `width` x `height` canvas,
+/// each stored pixel covering a `block` x `block` square.
+pub(crate) struct Stored {
+    format: PixelFormat,
+    width: usize,
+    height: usize,
+    w: usize,
+    h: usize,
+    block: usize,
+    len: usize,
+}
+
+/// Why the pixels of a canvas can not be laid out.
+#[derive(Debug)]
+pub(crate) enum LayoutError {
+    /// The depth or scale is not supported.
+    UnsupportedFormat,
+    /// The stored or decoded pixels take more bytes than `usize` holds.
+    TooLarge,
+}
+
+impl Stored {
+    /// Get the layout of a canvas with given depth and log2 scale.
+    pub(crate) fn new(width: u32, height: u32, depth: u16, scale: u8) -> Result<Self, LayoutError> {
+        let (format, folded) = PixelFormat::from_depth(depth).ok_or(LayoutError::UnsupportedFormat)?;
+        let scale = scale.checked_add(folded).filter(|&scale| scale <= 15).ok_or(LayoutError::UnsupportedFormat)?;
+        let (width, height) = (width as usize, height as usize);
+        let block = 1usize << scale;
+        let (w, h) = (width.div_ceil(block), height.div_ceil(block));
+        let len = format.stored_len(w, h).ok_or(LayoutError::TooLarge)?;
+        // the decoded pixels are BGRA8888 of the full size
+        width.checked_mul(height).and_then(|n| n.checked_mul(4)).ok_or(LayoutError::TooLarge)?;
+        Ok(Stored { format, width, height, w, h, block, len })
+    }
+
+    /// Get the bytes taken by the stored pixels.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Decode the stored pixels, at least [`Stored::len`] bytes, to BGRA8888 upscaled to the canvas size.
+    pub(crate) fn decode(&self, raw: &[u8]) -> Vec<u8> {
+        let raw = &raw[..self.len()];
+        let (w, h) = (self.w, self.h);
+        let pixels = match self.format {
+            PixelFormat::Argb4444 => {
+                raw.chunks_exact(2).flat_map(|p| argb4444(u16::from_le_bytes([p[0], p[1]]))).collect()
+            }
+            PixelFormat::Argb8888 => raw.to_vec(),
+            PixelFormat::Argb1555 => {
+                raw.chunks_exact(2).flat_map(|p| argb1555(u16::from_le_bytes([p[0], p[1]]))).collect()
+            }
+            PixelFormat::Rgb565 => raw.chunks_exact(2).flat_map(|p| rgb565(u16::from_le_bytes([p[0], p[1]]))).collect(),
+            PixelFormat::Dxt3 => dxt(raw, w, h, dxt3_alpha),
+            PixelFormat::Dxt5 => dxt(raw, w, h, dxt5_alpha),
+        };
+        upscale(pixels, w, h, self.width, self.height, self.block)
+    }
+}
+
+impl ImageBuffer<'_> {
+    /// Get the format the canvas is stored in, [`None`] for an unknown depth.
+    pub fn format(&self) -> Option<PixelFormat> {
+        PixelFormat::from_depth(self.depth).map(|(format, _)| format)
+    }
+
+    /// Convert the BGRA8888 pixels to RGBA8888, `width` x `height` x 4 bytes.
+    /// Fail with [`WzError::Corrupt`] with an empty path when `data` is not of that size.
+    pub fn to_rgba8(&self) -> Result<Vec<u8>> {
+        if self.data.len() != self.width as usize * self.height as usize * 4 {
+            return Err(WzError::Corrupt { path: String::new() });
+        }
+        Ok(self.data.chunks_exact(4).flat_map(|p| [p[2], p[1], p[0], p[3]]).collect())
+    }
+}
+
+impl ImageBuffer<'static> {
+    /// Decode the inflated pixels of a canvas as stored in the file, in the format of `info.depth`
+    /// and downsampled by `2^info.scale`, to BGRA8888 of the full size as returned by
+    /// [`MapleNode::img`](crate::node::MapleNode::img). Fail with [`WzError::UnsupportedFormat`]
+    /// for an unknown depth and [`WzError::Corrupt`] when `stored` is too short or the size overflows,
+    /// both with an empty path.
+    pub fn from_stored(info: ImageInfo, stored: &[u8]) -> Result<Self> {
+        let ImageInfo { width, height, depth, scale } = info;
+        let layout = Stored::new(width, height, depth, scale).map_err(|e| match e {
+            LayoutError::UnsupportedFormat => WzError::UnsupportedFormat { path: String::new(), format: depth as u32 },
+            LayoutError::TooLarge => WzError::Corrupt { path: String::new() },
+        })?;
+        if stored.len() < layout.len() {
+            return Err(WzError::Corrupt { path: String::new() });
+        }
+        Ok(ImageBuffer { data: layout.decode(stored).into(), width, height, depth, scale })
+    }
+}
+
+#[cfg(feature = "image")]
+impl ImageBuffer<'_> {
+    /// Convert the pixels to an [`RgbaImage`], see [`ImageBuffer::to_rgba8`].
+    pub fn to_rgba_image(&self) -> Result<RgbaImage> {
+        let pixels = self.to_rgba8()?;
+        RgbaImage::from_raw(self.width, self.height, pixels).ok_or(WzError::Corrupt { path: String::new() })
+    }
+
+    /// Convert the pixels to a [`DynamicImage`], see [`ImageBuffer::to_rgba8`].
+    pub fn to_dynamic_image(&self) -> Result<DynamicImage> {
+        self.to_rgba_image().map(DynamicImage::ImageRgba8)
+    }
+
+    /// Save the pixels as a PNG file.
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.save(path.as_ref(), ImageFormat::Png)
+    }
+
+    /// Save the pixels as a lossless WebP file.
+    pub fn save_webp<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.save(path.as_ref(), ImageFormat::WebP)
+    }
+
+    fn save(&self, path: &Path, format: ImageFormat) -> Result<()> {
+        self.to_rgba_image()?
+            .save_with_format(path, format)
+            .map_err(|e| WzError::Export { path: path.display().to_string(), reason: e.to_string() })
+    }
+}
+
+fn expand<const BITS: u32>(v: u16) -> u8 {
+    let max = (1u32 << BITS) - 1;
+    ((v as u32 & max) * 255 / max) as u8
+}
+
+fn argb4444(p: u16) -> [u8; 4] {
+    [expand::<4>(p), expand::<4>(p >> 4), expand::<4>(p >> 8), expand::<4>(p >> 12)]
+}
+
+fn argb1555(p: u16) -> [u8; 4] {
+    [expand::<5>(p), expand::<5>(p >> 5), expand::<5>(p >> 10), expand::<1>(p >> 15)]
+}
+
+fn rgb565(p: u16) -> [u8; 4] {
+    [expand::<5>(p), expand::<6>(p >> 5), expand::<5>(p >> 11), 0xFF]
+}
+
+/// Decode DXT blocks of 16 bytes, whose first 8 bytes hold alpha decoded by `alpha`.
+fn dxt(raw: &[u8], w: usize, h: usize, alpha: fn(&[u8]) -> [u8; 16]) -> Vec<u8> {
+    let mut pixels = vec![0u8; w * h * 4];
+    let blocks_w = w.div_ceil(4);
+    for (i, block) in raw.chunks_exact(16).enumerate() {
+        let (bx, by) = (i % blocks_w * 4, i / blocks_w * 4);
+        let alphas = alpha(&block[..8]);
+        let c0 = u16::from_le_bytes([block[8], block[9]]);
+        let c1 = u16::from_le_bytes([block[10], block[11]]);
+        let (p0, p1) = (rgb565(c0), rgb565(c1));
+        let mix = |a: u8, b: u8| ((2 * a as u16 + b as u16) / 3) as u8;
+        let colors = [
+            p0,
+            p1,
+            [mix(p0[0], p1[0]), mix(p0[1], p1[1]), mix(p0[2], p1[2]), 0xFF],
+            [mix(p1[0], p0[0]), mix(p1[1], p0[1]), mix(p1[2], p0[2]), 0xFF],
+        ];
+        let indices = u32::from_le_bytes([block[12], block[13], block[14], block[15]]);
+        for j in 0..16 {
+            let (x, y) = (bx + j % 4, by + j / 4);
+            if x >= w || y >= h {
+                continue;
+            }
+            let mut color = colors[(indices >> (2 * j) & 3) as usize];
+            color[3] = alphas[j];
+            let at = (y * w + x) * 4;
+            pixels[at..at + 4].copy_from_slice(&color);
+        }
+    }
+    pixels
+}
+
+fn dxt3_alpha(block: &[u8]) -> [u8; 16] {
+    let bits = u64::from_le_bytes(block.try_into().unwrap_or_default());
+    let mut alphas = [0u8; 16];
+    for (j, a) in alphas.iter_mut().enumerate() {
+        *a = expand::<4>((bits >> (4 * j)) as u16);
+    }
+    alphas
+}
+
+fn dxt5_alpha(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u16, block[1] as u16);
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 0xFF];
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i) as u16 * a0 + i as u16 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i) as u16 * a0 + i as u16 * a1) / 5;
+        }
+        palette[6] = 0;
+    }
+    let mut bytes = [0u8; 8];
+    bytes[..6].copy_from_slice(&block[2..8]);
+    let bits = u64::from_le_bytes(bytes);
+    let mut alphas = [0u8; 16];
+    for (j, a) in alphas.iter_mut().enumerate() {
+        *a = palette[(bits >> (3 * j) & 7) as usize] as u8;
+    }
+    alphas
+}
+
+/// Repeat each pixel of a `w` x `h` image into a `block` x `block` square, cropped to `width` x `height`.
+fn upscale(pixels: Vec<u8>, w: usize, h: usize, width: usize, height: usize, block: usize) -> Vec<u8> {
+    if block == 1 && (w, h) == (width, height) {
+        return pixels;
+    }
+    let mut out = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        let row = y / block * w;
+        for x in 0..width {
+            let at = (row + x / block) * 4;
+            out.extend_from_slice(&pixels[at..at + 4]);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode a 4 x 4 canvas stored with depth.
+    fn decode(depth: u16, raw: &[u8]) -> Vec<[u8; 4]> {
+        let stored = Stored::new(4, 4, depth, 0).unwrap();
+        assert_eq!(stored.len(), raw.len());
+        stored.decode(raw).chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]).collect()
+    }
+
+    #[test]
+    fn argb4444() {
+        let raw: Vec<u8> = [0xF0A5u16, 0x0F00].iter().cycle().take(16).flat_map(|p| p.to_le_bytes()).collect();
+        let pixels = decode(1, &raw);
+        assert_eq!(pixels[0], [85, 170, 0, 255]);
+        assert_eq!(pixels[1], [0, 0, 255, 0]);
+    }
+
+    #[test]
+    fn rgb565_and_argb1555() {
+        let raw: Vec<u8> = [0xF81Fu16, 0x07E0].iter().cycle().take(16).flat_map(|p| p.to_le_bytes()).collect();
+        let pixels = decode(513, &raw);
+        assert_eq!(pixels[0], [255, 0, 255, 255]);
+        assert_eq!(pixels[1], [0, 255, 0, 255]);
+        let raw: Vec<u8> = [0xFC00u16, 0x001F].iter().cycle().take(16).flat_map(|p| p.to_le_bytes()).collect();
+        let pixels = decode(257, &raw);
+        assert_eq!(pixels[0], [0, 0, 255, 255]);
+        assert_eq!(pixels[1], [255, 0, 0, 0]);
+    }
+
+    /// The colors of a DXT block: red and blue, indexed 0, 1, 2, 3 by its first 4 pixels and 0 by the others.
+    fn colors() -> [u8; 8] {
+        let mut colors = [0u8; 8];
+        colors[..2].copy_from_slice(&0xF800u16.to_le_bytes());
+        colors[2..4].copy_from_slice(&0x001Fu16.to_le_bytes());
+        colors[4] = 0b1110_0100;
+        colors
+    }
+
+    #[test]
+    fn dxt3() {
+        let mut block = vec![0x10, 0x32, 0x54, 0x76, 0x98, 0xBA, 0xDC, 0xFE];
+        block.extend_from_slice(&colors());
+        let pixels = decode(1026, &block);
+        assert_eq!(
+            &pixels[..5],
+            [[0, 0, 255, 0], [255, 0, 0, 17], [85, 0, 170, 34], [170, 0, 85, 51], [0, 0, 255, 68]]
+        );
+        assert_eq!(pixels[15][3], 255);
+    }
+
+    #[test]
+    fn dxt5() {
+        // pixel j picks alpha j % 8 of the palette
+        let bits = (0..16).fold(0u64, |bits, j| bits | (j % 8) << (3 * j));
+        for (alphas, expected) in
+            [([255, 0], [255, 0, 218, 182, 145, 109, 72, 36]), ([0, 100], [0, 100, 20, 40, 60, 80, 0, 255])]
+        {
+            let mut block = alphas.to_vec();
+            block.extend_from_slice(&bits.to_le_bytes()[..6]);
+            block.extend_from_slice(&colors());
+            let pixels = decode(2050, &block);
+            let decoded: Vec<u8> = pixels.iter().map(|p| p[3]).collect();
+            assert_eq!(decoded[..8], expected);
+            assert_eq!(decoded[8..], expected);
+            assert_eq!(pixels[1][..3], [255, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn scaled() {
+        // depth 517 folds a scale of 4, one stored pixel for 16 x 16
+        let info = ImageInfo { width: 20, height: 3, depth: 517, scale: 0 };
+        let image = ImageBuffer::from_stored(info, &[0x1F, 0x00, 0x00, 0xF8]).unwrap();
+        let rgba = image.to_rgba8().unwrap();
+        assert_eq!(rgba.len(), 20 * 3 * 4);
+        assert_eq!(rgba[..4], [0, 0, 255, 255]);
+        assert_eq!(rgba[16 * 4..17 * 4], [255, 0, 0, 255]);
+        assert!(matches!(ImageBuffer::from_stored(info, &[0; 3]), Err(WzError::Corrupt { .. })));
+        let unknown = ImageInfo { depth: 4, ..info };
+        assert!(matches!(ImageBuffer::from_stored(unknown, &[]), Err(WzError::UnsupportedFormat { format: 4, .. })));
+        assert!(matches!(ImageBuffer { data: vec![0; 4].into(), ..image }.to_rgba8(), Err(WzError::Corrupt { .. })));
+    }
+
+    #[test]
+    fn too_large() {
+        assert_eq!(PixelFormat::Argb8888.stored_len(4, 4), Some(64));
+        assert_eq!(PixelFormat::Argb8888.stored_len(usize::MAX / 2, 3), None);
+        assert_eq!(PixelFormat::Dxt5.stored_len(usize::MAX, usize::MAX), None);
+        assert!(matches!(Stored::new(u32::MAX, u32::MAX, 2, 0), Err(LayoutError::TooLarge)));
+        let info = ImageInfo { width: u32::MAX, height: u32::MAX, depth: 2, scale: 0 };
+        assert!(matches!(ImageBuffer::from_stored(info, &[]), Err(WzError::Corrupt { .. })));
+    }
+
+    #[test]
+    #[cfg(all(feature = "image", feature = "pure"))]
+    fn fixture_canvas() {
+        use crate::{node::MapleNode, pure::fixture};
+        let file = fixture::open();
+        let canvas = file.open_root().child("Test.img/canvas");
+        // the fixture stores BGRA bytes 0..16
+        let expected: Vec<u8> =
+            (0..16u8).collect::<Vec<_>>().chunks_exact(4).flat_map(|p| [p[2], p[1], p[0], p[3]]).collect();
+        let image = canvas.rgba_image().unwrap();
+        assert_eq!((image.dimensions(), image.as_raw()), ((2, 2), &expected));
+        assert_eq!(canvas.img().unwrap().to_rgba_image().unwrap(), image);
+
+        let path = std::env::temp_dir().join(format!("wz-canvas-{}.png", std::process::id()));
+        canvas.img().unwrap().save_png(&path).unwrap();
+        let read = image::open(&path).map(|image| image.to_rgba8());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.unwrap(), image);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::pure::image::Image;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+/// The parsed images of a file by offset, evicting the least recently used ones beyond a memory budget.
+/// Evicted images stay alive as long as a node of them does.
+pub(crate) struct ImageCache {
+    images: HashMap<usize, Cached>,
+    /// Offsets of the images, least recently used first, with the use they were queued at.
+    /// Entries of images used or removed since are stale and skipped.
+    lru: VecDeque<(usize, u64)>,
+    budget: Option<usize>,
+    /// The sum of the memory of the images, as last measured.
+    usage: usize,
+    clock: u64,
+}
+
+struct Cached {
+    image: Arc<Image>,
+    used: u64,
+    memory: usize,
+}
+
+impl ImageCache {
+    pub(crate) fn new() -> Self {
+        ImageCache { images: HashMap::new(), lru: VecDeque::new(), budget: None, usage: 0, clock: 0 }
+    }
+
+    pub(crate) fn get(&mut self, offset: usize) -> Option<Arc<Image>> {
+        let image = self.images.get(&offset)?.image.clone();
+        self.touch(offset);
+        Some(image)
+    }
+
+    /// Cache image unless another thread parsed it first, then trim the cache.
+    pub(crate) fn insert(&mut self, offset: usize, image: Image) -> Arc<Image> {
+        let usage = &mut self.usage;
+        let cached = self.images.entry(offset).or_insert_with(|| {
+            let memory = image.memory();
+            *usage += memory;
+            Cached { image: Arc::new(image), used: 0, memory }
+        });
+        let image = cached.image.clone();
+        self.touch(offset);
+        self.trim();
+        image
+    }
+
+    /// Measure the image at offset again after canvases of it were decoded, then trim the cache.
+    pub(crate) fn resize(&mut self, offset: usize) {
+        if let Some(cached) = self.images.get_mut(&offset) {
+            let memory = cached.image.memory();
+            self.usage = self.usage - cached.memory + memory;
+            cached.memory = memory;
+        }
+        self.trim();
+    }
+
+    pub(crate) fn remove(&mut self, offset: usize) {
+        if let Some(cached) = self.images.remove(&offset) {
+            self.usage -= cached.memory;
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.images.clear();
+        self.lru.clear();
+        self.usage = 0;
+    }
+
+    pub(crate) fn set_budget(&mut self, budget: Option<usize>) {
+        self.budget = budget;
+        self.trim();
+    }
+
+    pub(crate) fn usage(&self) -> usize {
+        self.usage
+    }
+
+    /// Mark the image at offset as the most recently used.
+    fn touch(&mut self, offset: usize) {
+        self.clock += 1;
+        if let Some(cached) = self.images.get_mut(&offset) {
+            cached.used = self.clock;
+            self.lru.push_back((offset, self.clock));
+        }
+        // stale entries pile up while the images fit the budget
+        if self.lru.len() > 2 * self.images.len() + 16 {
+            let images = &self.images;
+            self.lru.retain(|(offset, used)| images.get(offset).is_some_and(|cached| cached.used == *used));
+        }
+    }
+
+    /// Evict the least recently used images until the usage fits the budget.
+    /// The most recently used image is kept even when it alone exceeds the budget.
+    fn trim(&mut self) {
+        let budget = match self.budget {
+            Some(budget) => budget,
+            None => return,
+        };
+        while self.usage > budget && self.images.len() > 1 {
+            let (offset, used) = match self.lru.pop_front() {
+                Some(entry) => entry,
+                None => break,
+            };
+            if self.images.get(&offset).is_some_and(|cached| cached.used == used) {
+                self.remove(offset);
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{
+    pixel::{LayoutError, Stored},
+    pure::crypto::WzKey,
+};
+use flate2::read::ZlibDecoder;
+use std::{convert::TryInto, io::Read};
+
+/// Second byte of the zlib header of canvases which are not split into encrypted chunks.
+const ZLIB_HEADERS: [u8; 4] = [0x01, 0x5E, 0x9C, 0xDA];
+
+pub(crate) enum CanvasError {
+    /// The pixels are not a valid zlib stream, or inflate to less than the image.
+    Decompression,
+    /// The pixel format is not known.
+    UnsupportedFormat(u32),
+    /// The size of the canvas overflows `usize`.
+    TooLarge,
+}
+
+/// Inflate and decode the pixels of a canvas to BGRA8888, upscaled to `width` x `height`.
+/// `format` is the pixel format and `scale` the log2 of the size of each stored pixel.
+pub(crate) fn decode(
+    compressed: &[u8],
+    key: &WzKey,
+    width: u32,
+    height: u32,
+    format: u16,
+    scale: u8,
+) -> Result<Vec<u8>, CanvasError> {
+    let stored = Stored::new(width, height, format, scale).map_err(|e| match e {
+        LayoutError::UnsupportedFormat => CanvasError::UnsupportedFormat(format as u32),
+        LayoutError::TooLarge => CanvasError::TooLarge,
+    })?;
+    let raw = inflate(compressed, key, stored.len()).ok_or(CanvasError::Decompression)?;
+    Ok(stored.decode(&raw))
+}
+
+/// Inflate `expected` bytes, joining the encrypted chunks of list wz canvases first.
+fn inflate(compressed: &[u8], key: &WzKey, expected: usize) -> Option<Vec<u8>> {
+    let stream = match compressed {
+        [0x78, second, ..] if ZLIB_HEADERS.contains(second) => compressed.to_vec(),
+        _ => {
+            let mut joined = vec![];
+            let mut rest = compressed;
+            while rest.len() >= 4 {
+                let len: usize = i32::from_le_bytes(rest[..4].try_into().ok()?).try_into().ok()?;
+                let mut chunk = rest.get(4..4 + len)?.to_vec();
+                key.decrypt(&mut chunk);
+                joined.extend_from_slice(&chunk);
+                rest = &rest[4 + len..];
+            }
+            joined
+        }
+    };
+    // expected comes from the header, so the output grows as it is inflated rather than being reserved up front
+    let mut raw = vec![];
+    // an error after enough output is trailing garbage, which some files have
+    let _ = ZlibDecoder::new(&*stream).take(expected as u64).read_to_end(&mut raw);
+    if raw.len() < expected {
+        return None;
+    }
+    Some(raw)
+}
@@ -0,0 +1,123 @@
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes256,
+};
+use std::borrow::Cow;
+
+/// IV of global (GMS) clients.
+const GMS_IV: [u8; 4] = [0x4D, 0x23, 0xC7, 0x2B];
+/// IV of korean and european (KMS, EMS) clients.
+const KMS_IV: [u8; 4] = [0xB9, 0x7D, 0x63, 0xE9];
+/// IV of unencrypted clients, whose key stream is all zeros.
+const ZERO_IV: [u8; 4] = [0; 4];
+
+/// The AES user key shared by all clients, trimmed from the 128 bytes key in the client:
+/// every 16th byte of it, placed at a stride of 4 bytes with zeros between.
+pub const USER_KEY: [u8; 32] = [
+    0x13, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0xB4, 0x00, 0x00, 0x00, //
+    0x1B, 0x00, 0x00, 0x00, 0x0F, 0x00, 0x00, 0x00, 0x33, 0x00, 0x00, 0x00, 0x52, 0x00, 0x00, 0x00,
+];
+
+/// The region of a client, which decides the IV its files are encrypted with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Region {
+    /// Global clients (GMS)
+    Gms,
+    /// Korean and european clients (KMS, EMS)
+    Kms,
+    /// Clients whose files are not encrypted, e.g. GMS after v149
+    Zero,
+    /// Any other IV
+    Custom([u8; 4]),
+}
+
+impl Region {
+    /// Get the IV files of region are encrypted with.
+    pub fn iv(&self) -> [u8; 4] {
+        match self {
+            Region::Gms => GMS_IV,
+            Region::Kms => KMS_IV,
+            Region::Zero => ZERO_IV,
+            Region::Custom(iv) => *iv,
+        }
+    }
+}
+
+/// Length of the key stream generated up front, enough for any string.
+const STREAM_LEN: usize = 0x10000;
+
+/// The key stream strings and list wz images are xor-ed with,
+/// made of AES blocks chained from the IV repeated four times.
+pub(crate) struct WzKey {
+    region: Region,
+    user_key: [u8; 32],
+    stream: Vec<u8>,
+}
+
+impl WzKey {
+    pub(crate) fn new(region: Region, user_key: [u8; 32]) -> Self {
+        let stream = generate(region.iv(), user_key, STREAM_LEN);
+        WzKey { region, user_key, stream }
+    }
+
+    pub(crate) fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Get the first `len` bytes of the key stream.
+    pub(crate) fn stream(&self, len: usize) -> Cow<'_, [u8]> {
+        match self.stream.get(..len) {
+            Some(stream) => Cow::Borrowed(stream),
+            None => Cow::Owned(generate(self.region.iv(), self.user_key, len)),
+        }
+    }
+
+    /// Xor data with the key stream.
+    pub(crate) fn decrypt(&self, data: &mut [u8]) {
+        let stream = self.stream(data.len());
+        data.iter_mut().zip(stream.iter()).for_each(|(b, k)| *b ^= k);
+    }
+}
+
+fn generate(iv: [u8; 4], user_key: [u8; 32], len: usize) -> Vec<u8> {
+    let mut stream = vec![0u8; len.div_ceil(16) * 16];
+    if iv == ZERO_IV {
+        stream.truncate(len);
+        return stream;
+    }
+    let cipher = Aes256::new(GenericArray::from_slice(&user_key));
+    let mut block = GenericArray::clone_from_slice(&[iv, iv, iv, iv].concat());
+    for chunk in stream.chunks_exact_mut(16) {
+        cipher.encrypt_block(&mut block);
+        chunk.copy_from_slice(&block);
+    }
+    stream.truncate(len);
+    stream
+}
+
+/// Get the hash of version used to decrypt offsets, and the encrypted version stored in the header.
+pub(crate) fn version_hash(version: u16) -> (u32, u16) {
+    let hash = version.to_string().bytes().fold(0u32, |h, c| h.wrapping_mul(32).wrapping_add(c as u32 + 1));
+    let encrypted = 0xFF ^ (hash >> 24) ^ (hash >> 16) ^ (hash >> 8) ^ hash;
+    (hash, (encrypted & 0xFF) as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_hash_of_known_clients() {
+        assert_eq!(version_hash(83), (1876, 172));
+        assert_eq!(version_hash(95).1, 142);
+    }
+
+    #[test]
+    fn key_streams() {
+        let gms = WzKey::new(Region::Gms, USER_KEY);
+        assert_eq!(gms.stream(8)[..], [0x96, 0xAE, 0x3F, 0xA4, 0x48, 0xFA, 0xDD, 0x90]);
+        // past the generated stream, the same blocks are generated on demand
+        assert_eq!(gms.stream(STREAM_LEN + 16)[..STREAM_LEN], gms.stream(STREAM_LEN)[..]);
+        assert!(WzKey::new(Region::Zero, USER_KEY).stream(64).iter().all(|&b| b == 0));
+    }
+}
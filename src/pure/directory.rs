@@ -0,0 +1,160 @@
+use crate::pure::{
+    crypto::{version_hash, WzKey},
+    reader::Reader,
+};
+use std::{convert::TryInto, sync::OnceLock};
+
+/// Highest version tried when guessing the version of a file.
+const MAX_VERSION: u16 = 1000;
+
+/// An entry of the directory tree of a wz file.
+pub(crate) struct DirEntry {
+    pub(crate) name: String,
+    pub(crate) kind: EntryKind,
+}
+
+pub(crate) enum EntryKind {
+    /// A directory whose entries are listed on first access, [`None`] when the listing is corrupt.
+    Dir { offset: usize, entries: OnceLock<Option<Vec<DirEntry>>> },
+    /// An `.img` whose properties are parsed on demand.
+    Img { offset: usize, size: usize },
+}
+
+impl DirEntry {
+    fn dir(name: String, offset: usize) -> Self {
+        DirEntry { name, kind: EntryKind::Dir { offset, entries: OnceLock::new() } }
+    }
+}
+
+/// The header and directory tree of a wz file.
+pub(crate) struct Directory {
+    pub(crate) version: u16,
+    start: u32,
+    hash: u32,
+    pub(crate) root: DirEntry,
+}
+
+impl Directory {
+    /// Get the entries of a directory, listing it on first access.
+    /// Return [`None`] when the entry is an image or the listing is corrupt.
+    pub(crate) fn entries<'d>(&self, dir: &'d DirEntry, data: &[u8], key: &WzKey) -> Option<&'d [DirEntry]> {
+        match &dir.kind {
+            EntryKind::Dir { offset, entries } => {
+                entries.get_or_init(|| list(data, self.start, *offset, key, Some(self.hash))).as_deref()
+            }
+            EntryKind::Img { .. } => None,
+        }
+    }
+
+    /// Count the names listed so far which satisfy `pred`, without listing more directories.
+    pub(crate) fn count_listed(&self, pred: impl Fn(&str) -> bool) -> usize {
+        let mut count = 0;
+        let mut pending = vec![&self.root];
+        while let Some(entry) = pending.pop() {
+            if let EntryKind::Dir { entries, .. } = &entry.kind {
+                for child in entries.get().into_iter().flatten().flatten() {
+                    count += pred(&child.name) as usize;
+                    pending.push(child);
+                }
+            }
+        }
+        count
+    }
+}
+
+/// Read the header, then find the version whose hash decrypts the offsets of the root directory
+/// and its subdirectories to images and listings.
+/// Deeper directories are only listed when visited.
+pub(crate) fn parse(data: &[u8], key: &WzKey) -> Option<Directory> {
+    let start = start(data)?;
+    let encrypted = Reader::new(data, start as usize).u16()?;
+    (0..=MAX_VERSION).filter(|&version| version_hash(version).1 == encrypted).find_map(|version| {
+        let hash = version_hash(version).0;
+        let root = DirEntry::dir(String::new(), start as usize + 2);
+        let directory = Directory { version, start, hash, root };
+        let entries = directory.entries(&directory.root, data, key)?;
+        let valid = entries.iter().all(|entry| match &entry.kind {
+            EntryKind::Img { offset, .. } => is_image(data, *offset),
+            EntryKind::Dir { .. } => directory
+                .entries(entry, data, key)
+                .is_some_and(|entries| entries.iter().all(|entry| is_image_or_dir(data, entry))),
+        });
+        if valid {
+            Some(directory)
+        } else {
+            None
+        }
+    })
+}
+
+fn is_image(data: &[u8], offset: usize) -> bool {
+    data.get(offset) == Some(&0x73)
+}
+
+fn is_image_or_dir(data: &[u8], entry: &DirEntry) -> bool {
+    match &entry.kind {
+        EntryKind::Img { offset, .. } => is_image(data, *offset),
+        EntryKind::Dir { offset, .. } => *offset < data.len(),
+    }
+}
+
+/// Read the names of the root directory, which do not depend on the version.
+pub(crate) fn root_names(data: &[u8], key: &WzKey) -> Option<Vec<String>> {
+    let start = start(data)?;
+    let entries = list(data, start, start as usize + 2, key, None)?;
+    Some(entries.into_iter().map(|entry| entry.name).collect())
+}
+
+/// Check the magic and get the start of data.
+pub(crate) fn start(data: &[u8]) -> Option<u32> {
+    let mut reader = Reader::new(data, 0);
+    if reader.bytes(4)? != b"PKG1" {
+        return None;
+    }
+    reader.skip(8)?;
+    reader.u32()
+}
+
+/// Read the listing of a directory at offset. Offsets are only decrypted when the version hash is known.
+fn list(data: &[u8], start: u32, offset: usize, key: &WzKey, hash: Option<u32>) -> Option<Vec<DirEntry>> {
+    let mut reader = Reader::new(data, offset);
+    let count: usize = reader.compressed_i32()?.try_into().ok()?;
+    if count > data.len().saturating_sub(offset) {
+        return None;
+    }
+    let mut list = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut kind = reader.u8()?;
+        let name = match kind {
+            1 => {
+                // unknown entry without name
+                reader.skip(4 + 2 + 4)?;
+                continue;
+            }
+            2 => {
+                // the type and name are stored elsewhere, relative to the start of data
+                let at: usize = reader.i32()?.try_into().ok()?;
+                let mut elsewhere = Reader::new(data, (start as usize).checked_add(at)?);
+                kind = elsewhere.u8()?;
+                elsewhere.string(key)?
+            }
+            3 | 4 => reader.string(key)?,
+            _ => return None,
+        };
+        let size: usize = reader.compressed_i32()?.try_into().ok()?;
+        let _checksum = reader.compressed_i32()?;
+        let offset = match hash {
+            Some(hash) => reader.offset(start, hash)?,
+            None => {
+                reader.skip(4)?;
+                0
+            }
+        };
+        match kind {
+            3 => list.push(DirEntry::dir(name, offset)),
+            4 => list.push(DirEntry { name, kind: EntryKind::Img { offset, size } }),
+            _ => return None,
+        }
+    }
+    Some(list)
+}
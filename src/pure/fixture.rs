@@ -0,0 +1,352 @@
+//! A small wz file written in memory for the tests of every module.
+//!
+//! The root holds directory `Mob` with `Mob.img` and `Test.img`. `Mob.img` holds `hp` = 10, `links`
+//! with UOLs `up` (to `../hp`), `dangling` (to `nothing`) and the cycle `a` and `b`, canvas `icon`
+//! with `z` = 3 and `a0` = 128, and the animation `stand`. Its children are, in file order, canvases `10`
+//! and `1` with delays 300 and 200, `zigzag` = 1, `2` (a UOL to `0`), canvas `0` with delay 100 and `repeat` = 1.
+//! `Test.img` properties are one of each type: `short`, `int`, `long`, `float`,
+//! `double`, `str`, `number` (the str "42"), `vec`, `convex`, `sub` (`x` = 42), `link` (a UOL to `sub/x`),
+//! `canvas`, `sound` (an MP3 header over data "audio") and `pcm` (16 bit stereo at 8000Hz).
+//!
+//! Every canvas is 2x2 BGRA8888 pixels 0..16. `Test.img/canvas` has the children of a frame: `origin` (5, 6),
+//! `head` (1, -20), `lt` (-3, -4), `rb` (3, 0), `map` with `navel` (0, -10) and `hand` (7, -8),
+//! `delay` = 120, `z` = "armOverHair", `a0` = 0 and `a1` = 240.
+
+use crate::pure::{
+    crypto::{version_hash, Region, WzKey, USER_KEY},
+    WzCtx, WzFile,
+};
+use flate2::{write::ZlibEncoder, Compression};
+use std::{convert::TryFrom, io::Write, sync::Arc};
+
+/// The version of the client writing the fixture.
+pub(crate) const VERSION: u16 = 83;
+
+/// Offsets of entries are encrypted with this constant, see `Reader::offset`.
+const OFFSET_CONSTANT: u32 = 0x581C_3F6D;
+
+/// A little endian writer encrypting strings with the key stream of a region.
+pub(crate) struct Writer {
+    pub(crate) buf: Vec<u8>,
+    stream: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn new(region: Region) -> Self {
+        Writer::with_user_key(region, USER_KEY)
+    }
+
+    pub(crate) fn with_user_key(region: Region, user_key: [u8; 32]) -> Self {
+        Writer { buf: vec![], stream: WzKey::new(region, user_key).stream(0x1000).into_owned() }
+    }
+
+    pub(crate) fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub(crate) fn u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn i16(&mut self, v: i16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn i32(&mut self, v: i32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn compressed_i32(&mut self, v: i32) {
+        match i8::try_from(v) {
+            Ok(v) if v != -128 => self.u8(v as u8),
+            _ => {
+                self.u8(0x80);
+                self.i32(v);
+            }
+        }
+    }
+
+    /// An encrypted string, latin1 when it is ascii and utf16 otherwise.
+    pub(crate) fn string(&mut self, s: &str) {
+        if s.is_ascii() {
+            match s.len() {
+                0 => self.u8(0),
+                len @ 1..=127 => self.u8((-(len as i32)) as u8),
+                len => {
+                    self.u8(0x80);
+                    self.i32(len as i32);
+                }
+            }
+            let mut mask: u8 = 0xAA;
+            for (c, k) in s.bytes().zip(self.stream.clone()) {
+                self.u8(c ^ k ^ mask);
+                mask = mask.wrapping_add(1);
+            }
+        } else {
+            let units: Vec<u16> = s.encode_utf16().collect();
+            match units.len() {
+                len @ 1..=126 => self.u8(len as u8),
+                len => {
+                    self.u8(127);
+                    self.i32(len as i32);
+                }
+            }
+            let mut mask: u16 = 0xAAAA;
+            for (i, unit) in units.into_iter().enumerate() {
+                self.u16(unit ^ mask ^ u16::from_le_bytes([self.stream[2 * i], self.stream[2 * i + 1]]));
+                mask = mask.wrapping_add(1);
+            }
+        }
+    }
+
+    /// A property of a plain type, whose value is written by body.
+    pub(crate) fn prop(&mut self, name: &str, tag: u8, body: impl FnOnce(&mut Writer)) {
+        self.u8(0);
+        self.string(name);
+        self.u8(tag);
+        body(self);
+    }
+
+    /// An extended property of class, whose size is filled in once body is written.
+    pub(crate) fn extended(&mut self, name: &str, class: &str, body: impl FnOnce(&mut Writer)) {
+        self.prop(name, 9, |w| {
+            let at = w.buf.len();
+            w.u32(0);
+            w.u8(0x73);
+            w.string(class);
+            body(w);
+            let size = (w.buf.len() - at - 4) as u32;
+            w.buf[at..at + 4].copy_from_slice(&size.to_le_bytes());
+        });
+    }
+
+    /// An entry of a directory listing, returning where its size and offset are filled in by [`Writer::fill`].
+    fn entry(&mut self, kind: u8, name: &str) -> usize {
+        self.u8(kind);
+        self.string(name);
+        let at = self.buf.len();
+        self.u8(0x80);
+        self.u32(0);
+        self.u8(0);
+        self.u32(0);
+        at
+    }
+
+    fn fill(&mut self, at: usize, size: usize, offset: usize, start: u32) {
+        self.buf[at + 1..at + 5].copy_from_slice(&(size as u32).to_le_bytes());
+        let encrypted = encrypt_offset((at + 6) as u32, start, version_hash(VERSION).0, offset as u32);
+        self.buf[at + 6..at + 10].copy_from_slice(&encrypted.to_le_bytes());
+    }
+}
+
+/// Encrypt the offset of an entry stored at pos, the inverse of `Reader::offset`.
+pub(crate) fn encrypt_offset(pos: u32, start: u32, hash: u32, offset: u32) -> u32 {
+    let mut key = pos.wrapping_sub(start) ^ u32::MAX;
+    key = key.wrapping_mul(hash).wrapping_sub(OFFSET_CONSTANT);
+    key = key.rotate_left(key & 0x1F);
+    key ^ offset.wrapping_sub(start.wrapping_mul(2))
+}
+
+pub(crate) fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Get the bytes of the fixture encrypted with the key of region.
+pub(crate) fn bytes(region: Region) -> Vec<u8> {
+    bytes_with_user_key(region, USER_KEY)
+}
+
+/// Get the bytes of the fixture encrypted with the key of region made from another AES user key.
+pub(crate) fn bytes_with_user_key(region: Region, user_key: [u8; 32]) -> Vec<u8> {
+    let mut w = Writer::with_user_key(region, user_key);
+    let description = b"Package file v1.0 Copyright 2002 Wizet, ZMS\0";
+    let start = (16 + description.len()) as u32;
+    w.buf.extend_from_slice(b"PKG1");
+    w.buf.extend_from_slice(&[0; 8]);
+    w.u32(start);
+    w.buf.extend_from_slice(description);
+    w.u16(version_hash(VERSION).1);
+
+    w.compressed_i32(2);
+    let mob = w.entry(3, "Mob");
+    let test = w.entry(4, "Test.img");
+    let mob_at = w.buf.len();
+    w.compressed_i32(1);
+    let mob_img = w.entry(4, "Mob.img");
+
+    let mob_img_at = w.buf.len();
+    w.u8(0x73);
+    w.string("Property");
+    w.u16(0);
+    w.compressed_i32(4);
+    w.prop("hp", 3, |w| w.compressed_i32(10));
+    w.extended("links", "Property", |w| {
+        w.u16(0);
+        w.compressed_i32(4);
+        for (name, link) in [("up", "../hp"), ("dangling", "nothing"), ("a", "b"), ("b", "a")] {
+            w.extended(name, "UOL", |w| {
+                w.u16(0);
+                w.string(link);
+            });
+        }
+    });
+    canvas(&mut w, "icon", 2, |w| {
+        w.prop("z", 3, |w| w.compressed_i32(3));
+        w.prop("a0", 3, |w| w.compressed_i32(128));
+    });
+    w.extended("stand", "Property", |w| {
+        w.u16(0);
+        w.compressed_i32(6);
+        for (name, delay) in [("10", 300), ("1", 200)] {
+            canvas(w, name, 1, |w| w.prop("delay", 3, |w| w.compressed_i32(delay)));
+        }
+        w.prop("zigzag", 3, |w| w.compressed_i32(1));
+        w.extended("2", "UOL", |w| {
+            w.u16(0);
+            w.string("0");
+        });
+        canvas(w, "0", 1, |w| w.prop("delay", 3, |w| w.compressed_i32(100)));
+        w.prop("repeat", 3, |w| w.compressed_i32(1));
+    });
+    let test_at = w.buf.len();
+    test_img(&mut w);
+    let end = w.buf.len();
+
+    w.fill(mob, 0, mob_at, start);
+    w.fill(test, end - test_at, test_at, start);
+    w.fill(mob_img, test_at - mob_img_at, mob_img_at, start);
+    let size = (end - start as usize) as u64;
+    w.buf[4..12].copy_from_slice(&size.to_le_bytes());
+    w.buf
+}
+
+fn test_img(w: &mut Writer) {
+    w.u8(0x73);
+    w.string("Property");
+    w.u16(0);
+    w.compressed_i32(14);
+    w.prop("short", 2, |w| w.i16(-7));
+    w.prop("int", 3, |w| w.compressed_i32(100_000));
+    w.prop("long", 20, |w| {
+        w.u8(0x80);
+        w.buf.extend_from_slice(&(1i64 << 40).to_le_bytes());
+    });
+    w.prop("float", 4, |w| {
+        w.u8(0x80);
+        w.buf.extend_from_slice(&1.5f32.to_le_bytes());
+    });
+    w.prop("double", 5, |w| w.buf.extend_from_slice(&2.25f64.to_le_bytes()));
+    w.prop("str", 8, |w| {
+        w.u8(0);
+        w.string("héllo");
+    });
+    w.prop("number", 8, |w| {
+        w.u8(0);
+        w.string("42");
+    });
+    w.extended("vec", "Shape2D#Vector2D", |w| {
+        w.compressed_i32(3);
+        w.compressed_i32(-4);
+    });
+    w.extended("convex", "Shape2D#Convex2D", |w| {
+        w.compressed_i32(2);
+        for (x, y) in [(1, 2), (300, -400)] {
+            w.u8(0x73);
+            w.string("Shape2D#Vector2D");
+            w.compressed_i32(x);
+            w.compressed_i32(y);
+        }
+    });
+    w.extended("sub", "Property", |w| {
+        w.u16(0);
+        w.compressed_i32(1);
+        w.prop("x", 3, |w| w.compressed_i32(42));
+    });
+    w.extended("link", "UOL", |w| {
+        w.u16(0);
+        w.string("sub/x");
+    });
+    canvas(w, "canvas", 9, |w| {
+        vector(w, "origin", 5, 6);
+        vector(w, "head", 1, -20);
+        vector(w, "lt", -3, -4);
+        vector(w, "rb", 3, 0);
+        w.extended("map", "Property", |w| {
+            w.u16(0);
+            w.compressed_i32(2);
+            vector(w, "navel", 0, -10);
+            vector(w, "hand", 7, -8);
+        });
+        w.prop("delay", 3, |w| w.compressed_i32(120));
+        w.prop("z", 8, |w| {
+            w.u8(0);
+            w.string("armOverHair");
+        });
+        w.prop("a0", 3, |w| w.compressed_i32(0));
+        w.prop("a1", 3, |w| w.compressed_i32(240));
+    });
+    w.extended("sound", "Sound_DX8", |w| {
+        w.u8(0);
+        w.compressed_i32(5);
+        w.compressed_i32(1234);
+        w.buf.extend_from_slice(&[0; 51]);
+        w.u8(18);
+        w.u16(0x55);
+        w.buf.extend_from_slice(&[0; 16]);
+        w.buf.extend_from_slice(b"audio");
+    });
+    w.extended("pcm", "Sound_DX8", |w| {
+        w.u8(0);
+        w.compressed_i32(8);
+        w.compressed_i32(1);
+        w.buf.extend_from_slice(&[0; 51]);
+        w.u8(18);
+        for v in [1u16, 2] {
+            w.u16(v);
+        }
+        w.u32(8000);
+        w.u32(32000);
+        for v in [4u16, 16, 0] {
+            w.u16(v);
+        }
+        w.buf.extend_from_slice(&[1, 0, 2, 0, 3, 0, 4, 0]);
+    });
+}
+
+/// A 2x2 BGRA8888 canvas of pixels 0..16 with `count` children written by body.
+fn canvas(w: &mut Writer, name: &str, count: i32, children: impl FnOnce(&mut Writer)) {
+    w.extended(name, "Canvas", |w| {
+        w.u8(0);
+        w.u8(1);
+        w.u16(0);
+        w.compressed_i32(count);
+        children(w);
+        w.compressed_i32(2);
+        w.compressed_i32(2);
+        w.compressed_i32(2);
+        w.u8(0);
+        w.u32(0);
+        let pixels = zlib(&(0..16).collect::<Vec<u8>>());
+        w.i32(pixels.len() as i32 + 1);
+        w.u8(0);
+        w.buf.extend_from_slice(&pixels);
+    });
+}
+
+fn vector(w: &mut Writer, name: &str, x: i32, y: i32) {
+    w.extended(name, "Shape2D#Vector2D", |w| {
+        w.compressed_i32(x);
+        w.compressed_i32(y);
+    });
+}
+
+/// Open the fixture encrypted with the key of GMS.
+pub(crate) fn open() -> WzFile {
+    WzCtx::new().unwrap().open_bytes(Arc::from(bytes(Region::Gms))).unwrap()
+}
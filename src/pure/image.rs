@@ -0,0 +1,322 @@
+use crate::{
+    pure::{crypto::WzKey, reader::Reader},
+    sound::WaveFormat,
+};
+use std::{
+    convert::TryInto,
+    mem::size_of,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+};
+
+/// Size of the media type, subtype and format GUIDs preceding the wave format of a sound.
+const SOUND_GUIDS_LEN: usize = 51;
+
+/// The deepest nesting of property lists parsed, beyond which an image is corrupt rather than overflowing the stack.
+const MAX_DEPTH: usize = 64;
+
+/// The properties of an `.img`, stored in an arena whose first property is the image itself.
+pub(crate) struct Image {
+    /// Position of the image in the file.
+    pub(crate) offset: usize,
+    pub(crate) props: Vec<Prop>,
+    /// Approximate bytes taken by the properties.
+    parsed: usize,
+    /// Bytes taken by the canvases decoded so far.
+    decoded: AtomicUsize,
+}
+
+pub(crate) struct Prop {
+    pub(crate) name: String,
+    pub(crate) value: Value,
+    pub(crate) children: Vec<usize>,
+}
+
+impl Prop {
+    fn memory(&self) -> usize {
+        let value = match &self.value {
+            Value::Str(s) | Value::Uol(s) => s.capacity(),
+            Value::Convex(points) => points.capacity() * size_of::<glam::IVec2>(),
+            _ => 0,
+        };
+        size_of::<Prop>() + self.name.capacity() + self.children.capacity() * size_of::<usize>() + value
+    }
+}
+
+pub(crate) enum Value {
+    Nil,
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Str(String),
+    Vec(i32, i32),
+    Uol(String),
+    Canvas(Canvas),
+    Convex(Vec<glam::IVec2>),
+    Sound(Sound),
+    Property,
+}
+
+pub(crate) struct Canvas {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) depth: u16,
+    pub(crate) scale: u8,
+    /// Position of the compressed pixels in the file.
+    pub(crate) offset: usize,
+    pub(crate) len: usize,
+    /// Decoded pixels, filled on first access.
+    pub(crate) pixels: OnceLock<Vec<u8>>,
+}
+
+pub(crate) struct Sound {
+    pub(crate) ms: u32,
+    /// The wave format, [`None`] when the sound has none.
+    pub(crate) wave_format: Option<WaveFormat>,
+    /// Position of the audio data in the file.
+    pub(crate) offset: usize,
+    pub(crate) len: usize,
+}
+
+pub(crate) enum ParseError {
+    /// The image does not start with a property list, which usually means a wrong key.
+    Header,
+    /// The image is truncated or malformed.
+    Corrupt,
+}
+
+impl Image {
+    /// Get the approximate bytes taken by the image and its decoded canvases.
+    pub(crate) fn memory(&self) -> usize {
+        self.parsed + self.decoded.load(Ordering::Relaxed)
+    }
+
+    /// Keep the decoded pixels of canvas `index`, unless another thread decoded them first.
+    pub(crate) fn decoded(&self, index: usize, pixels: Vec<u8>) -> &[u8] {
+        let canvas = match &self.props[index].value {
+            Value::Canvas(canvas) => canvas,
+            _ => return &[],
+        };
+        let len = pixels.capacity();
+        if canvas.pixels.set(pixels).is_ok() {
+            self.decoded.fetch_add(len, Ordering::Relaxed);
+        }
+        canvas.pixels.get().map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Find the child of property `parent` with given name.
+    pub(crate) fn find(&self, parent: usize, name: &str) -> Option<usize> {
+        self.props[parent].children.iter().copied().find(|&c| self.props[c].name == name)
+    }
+}
+
+/// Parse the properties of the `.img` at offset.
+pub(crate) fn parse(data: &[u8], offset: usize, key: &WzKey) -> Result<Image, ParseError> {
+    let mut reader = Reader::new(data, offset);
+    let header = (|| Some(reader.u8()? == 0x73 && reader.string(key)? == "Property" && reader.u16()? == 0))();
+    if header != Some(true) {
+        return Err(ParseError::Header);
+    }
+    let mut parser = Parser { key, base: offset, props: vec![], depth: 0 };
+    let root = parser.push(None, String::new(), Value::Property);
+    parser.property_list(&mut reader, root).ok_or(ParseError::Corrupt)?;
+    let parsed = parser.props.iter().map(Prop::memory).sum();
+    Ok(Image { offset, props: parser.props, parsed, decoded: AtomicUsize::new(0) })
+}
+
+struct Parser<'k> {
+    key: &'k WzKey,
+    /// Start of the image, which string offsets are relative to.
+    base: usize,
+    props: Vec<Prop>,
+    /// The number of property lists being parsed.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn push(&mut self, parent: Option<usize>, name: String, value: Value) -> usize {
+        let index = self.props.len();
+        self.props.push(Prop { name, value, children: vec![] });
+        if let Some(parent) = parent {
+            self.props[parent].children.push(index);
+        }
+        index
+    }
+
+    fn property_list(&mut self, reader: &mut Reader, parent: usize) -> Option<()> {
+        if self.depth == MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let parsed = self.properties(reader, parent);
+        self.depth -= 1;
+        parsed
+    }
+
+    fn properties(&mut self, reader: &mut Reader, parent: usize) -> Option<()> {
+        let count = reader.compressed_i32()?;
+        for _ in 0..count {
+            let name = reader.string_block(self.base, self.key)?;
+            let value = match reader.u8()? {
+                0 => Value::Nil,
+                2 | 11 => Value::Short(reader.i16()?),
+                3 | 19 => Value::Int(reader.compressed_i32()?),
+                20 => Value::Long(reader.compressed_i64()?),
+                4 => match reader.u8()? {
+                    0x80 => Value::Float(reader.f32()?),
+                    _ => Value::Float(0.0),
+                },
+                5 => Value::Double(reader.f64()?),
+                8 => Value::Str(reader.string_block(self.base, self.key)?),
+                9 => {
+                    let size = reader.u32()? as usize;
+                    let end = reader.pos().checked_add(size)?;
+                    self.extended(reader, parent, name)?;
+                    reader.seek(end);
+                    continue;
+                }
+                _ => return None,
+            };
+            self.push(Some(parent), name, value);
+        }
+        Some(())
+    }
+
+    /// The class name of an extended property, inline or referenced.
+    fn class(&self, reader: &mut Reader) -> Option<String> {
+        match reader.u8()? {
+            0x00 | 0x73 => reader.string(self.key),
+            0x01 | 0x1B => {
+                let offset: usize = reader.i32()?.try_into().ok()?;
+                reader.string_at(self.base.checked_add(offset)?, self.key)
+            }
+            _ => None,
+        }
+    }
+
+    fn extended(&mut self, reader: &mut Reader, parent: usize, name: String) -> Option<()> {
+        match &*self.class(reader)? {
+            "Property" => {
+                reader.skip(2)?;
+                let index = self.push(Some(parent), name, Value::Property);
+                self.property_list(reader, index)?;
+            }
+            "Canvas" => {
+                reader.skip(1)?;
+                let index = self.push(Some(parent), name, Value::Nil);
+                if reader.u8()? == 1 {
+                    reader.skip(2)?;
+                    self.property_list(reader, index)?;
+                }
+                let width = reader.compressed_i32()?.try_into().ok()?;
+                let height = reader.compressed_i32()?.try_into().ok()?;
+                let depth = reader.compressed_i32()?.try_into().ok()?;
+                let scale = reader.u8()?;
+                reader.skip(4)?;
+                let len: usize = reader.i32()?.checked_sub(1)?.try_into().ok()?;
+                reader.skip(1)?;
+                let offset = reader.pos();
+                reader.skip(len)?;
+                let pixels = OnceLock::new();
+                self.props[index].value = Value::Canvas(Canvas { width, height, depth, scale, offset, len, pixels });
+            }
+            "Shape2D#Vector2D" => {
+                let (x, y) = (reader.compressed_i32()?, reader.compressed_i32()?);
+                self.push(Some(parent), name, Value::Vec(x, y));
+            }
+            "Shape2D#Convex2D" => {
+                let count = reader.compressed_i32()?;
+                let mut points = vec![];
+                for _ in 0..count {
+                    if self.class(reader)? != "Shape2D#Vector2D" {
+                        return None;
+                    }
+                    points.push(glam::IVec2::new(reader.compressed_i32()?, reader.compressed_i32()?));
+                }
+                self.push(Some(parent), name, Value::Convex(points));
+            }
+            "Sound_DX8" => {
+                reader.skip(1)?;
+                let len: usize = reader.compressed_i32()?.try_into().ok()?;
+                let ms = reader.compressed_i32()?.try_into().ok()?;
+                reader.skip(SOUND_GUIDS_LEN)?;
+                let format_len = reader.u8()? as usize;
+                let wave_format = wave_format(reader.bytes(format_len)?, self.key);
+                let offset = reader.pos();
+                reader.skip(len)?;
+                self.push(Some(parent), name, Value::Sound(Sound { ms, wave_format, offset, len }));
+            }
+            "UOL" => {
+                reader.skip(1)?;
+                let link = match reader.u8()? {
+                    0 => reader.string(self.key)?,
+                    1 => {
+                        let offset: usize = reader.i32()?.try_into().ok()?;
+                        reader.string_at(self.base.checked_add(offset)?, self.key)?
+                    }
+                    _ => return None,
+                };
+                self.push(Some(parent), name, Value::Uol(link));
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+/// Parse a WAVEFORMATEX, which some clients encrypt.
+/// The format is plain when its `cbSize` accounts for the remaining bytes.
+fn wave_format(format: &[u8], key: &WzKey) -> Option<WaveFormat> {
+    if format.len() < 18 {
+        return None;
+    }
+    let plain = |f: &[u8]| u16::from_le_bytes([f[16], f[17]]) as usize + 18 == f.len();
+    if plain(format) {
+        return WaveFormat::parse(format);
+    }
+    let mut decrypted = format.to_vec();
+    key.decrypt(&mut decrypted);
+    WaveFormat::parse(&decrypted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pure::{
+        crypto::{Region, USER_KEY},
+        fixture::Writer,
+    };
+
+    /// An image of `depth` property lists nested in each other, the innermost holding an int.
+    fn nested(depth: usize) -> Vec<u8> {
+        fn list(w: &mut Writer, depth: usize) {
+            w.compressed_i32(1);
+            if depth == 1 {
+                w.prop("x", 3, |w| w.compressed_i32(1));
+            } else {
+                w.extended("p", "Property", |w| {
+                    w.u16(0);
+                    list(w, depth - 1);
+                });
+            }
+        }
+        let mut w = Writer::new(Region::Gms);
+        w.u8(0x73);
+        w.string("Property");
+        w.u16(0);
+        list(&mut w, depth);
+        w.buf
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let key = WzKey::new(Region::Gms, USER_KEY);
+        let image = parse(&nested(MAX_DEPTH), 0, &key).ok().unwrap();
+        assert_eq!(image.props.len(), MAX_DEPTH + 1);
+        assert!(matches!(parse(&nested(MAX_DEPTH + 1), 0, &key), Err(ParseError::Corrupt)));
+    }
+}
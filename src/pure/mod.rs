@@ -0,0 +1,1016 @@
+//! A wz reader written in Rust, enabled by feature `pure` in place of libwz.
+//!
+//! Files are read whole into memory or mapped. Each directory is listed on first access,
+//! the properties of each `.img` are parsed on first access and the pixels of each canvas when requested.
+//! The compressed payloads of canvases and sounds are borrowed from the file without copying.
+//!
+//! Some features only exist on this backend, because libwz has no API for them:
+//! - choosing the keys of a context with [`WzCtxBuilder`], and reading the one of a file with [`WzFile::region`].
+//! - reading bytes in place with [`WzCtx::open_bytes`] and mapping a file with [`WzCtx::open_mmap`].
+//!   libwz opens the bytes of `open_bytes` and `open_reader` from a temporary file, and can not map files.
+//! - parsing a memory map lazily, and borrowing the stored payloads of canvases and sounds
+//!   with [`WzNode::raw_img`] and [`WzNode::raw_audio`]. libwz reads the file through buffered reads
+//!   and only hands out decoded pixels.
+
+mod cache;
+mod canvas;
+mod crypto;
+mod directory;
+#[cfg(test)]
+pub(crate) mod fixture;
+mod image;
+mod reader;
+mod source;
+
+use crate::{
+    error::{Result, WzError},
+    node::{fmt_node, follow_links, AudioBuffer, Dtype, ImageBuffer, ImageInfo, MapleNode},
+    path::WzPath,
+};
+use cache::ImageCache;
+use canvas::CanvasError;
+use crypto::WzKey;
+pub use crypto::{Region, USER_KEY};
+use directory::{DirEntry, Directory, EntryKind};
+use image::{Image, ParseError, Value};
+use source::Source;
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    fmt::{Debug, Formatter},
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+/// Where a node lives: an entry of the directory tree, or a property of a parsed `.img`.
+#[derive(Clone)]
+enum Loc<'f> {
+    Entry(&'f DirEntry),
+    Prop(Arc<Image>, usize),
+}
+
+/// The children of a container node.
+enum Children<'f> {
+    Dir(&'f [DirEntry]),
+    Props(Arc<Image>, usize),
+}
+
+/// A node of an opened [`WzFile`], which can not outlive the file.
+/// Using a node after its file is dropped does not compile:
+///
+/// ```compile_fail,E0597
+/// use wz::{node::MapleNode, pure::WzCtx};
+///
+/// let ctx = WzCtx::new()?;
+/// let root = {
+///     let file = ctx.open_file("Mob.wz")?;
+///     file.open_root()?
+/// };
+/// println!("{}", root.len());
+/// # Ok::<(), wz::error::WzError>(())
+/// ```
+///
+/// Neither does using a str of a node after the node is dropped:
+///
+/// ```compile_fail,E0597
+/// use wz::{node::MapleNode, pure::WzCtx};
+///
+/// let ctx = WzCtx::new()?;
+/// let file = ctx.open_file("String.wz")?;
+/// let name = {
+///     let node = file.open_root()?.child("Mob.img/100100/name")?;
+///     node.str()?
+/// };
+/// println!("{}", name);
+/// # Ok::<(), wz::error::WzError>(())
+/// ```
+pub struct WzNode<'f> {
+    file: &'f WzFile,
+    loc: Loc<'f>,
+    pub path: WzPath,
+    follow_uol: bool,
+}
+
+impl<'f> WzNode<'f> {
+    fn new(file: &'f WzFile, loc: Loc<'f>, path: WzPath) -> Self {
+        WzNode { file, loc, path, follow_uol: false }
+    }
+
+    /// Make [`MapleNode::child`] transparently follow [`Dtype::UOL`] nodes met on the way.
+    /// The mode is inherited by every node opened from this one.
+    pub fn set_follow_uol(&mut self, follow: bool) {
+        self.follow_uol = follow;
+    }
+
+    /// Whether [`MapleNode::child`] follows [`Dtype::UOL`] nodes.
+    pub fn follows_uol(&self) -> bool {
+        self.follow_uol
+    }
+
+    /// Evict the parsed `.img` containing node from its file, along with its decoded canvases.
+    /// The memory is freed once every node of the image is dropped; visiting it again parses it anew.
+    /// Directories are not affected.
+    pub fn unload(&self) {
+        let offset = match &self.loc {
+            Loc::Prop(image, _) => image.offset,
+            Loc::Entry(DirEntry { kind: EntryKind::Img { offset, .. }, .. }) => *offset,
+            Loc::Entry(_) => return,
+        };
+        self.file.images().remove(offset);
+    }
+
+    /// Get the pixels of node with type [`Dtype::IMG`] exactly as stored, borrowed from the file:
+    /// a zlib stream, or chunks of one encrypted with the key of file for list wz files.
+    /// See [`MapleNode::img`] for the decoded pixels. Only this backend keeps the stored pixels.
+    pub fn raw_img(&self) -> Result<&'f [u8]> {
+        match self.prop() {
+            Some(Value::Canvas(canvas)) => self.payload(canvas.offset, canvas.len),
+            _ => Err(self.mismatch(Dtype::IMG)),
+        }
+    }
+
+    /// Get the data of node with type [`Dtype::AO`] borrowed from the file,
+    /// which unlike [`MapleNode::audio`] may outlive the node. Only this backend lends data beyond the node.
+    pub fn raw_audio(&self) -> Result<&'f [u8]> {
+        match self.prop() {
+            Some(Value::Sound(sound)) => self.payload(sound.offset, sound.len),
+            _ => Err(self.mismatch(Dtype::AO)),
+        }
+    }
+
+    fn payload(&self, offset: usize, len: usize) -> Result<&'f [u8]> {
+        let file = self.file;
+        offset
+            .checked_add(len)
+            .and_then(|end| file.data.get(offset..end))
+            .ok_or_else(|| WzError::Corrupt { path: self.path_str().to_owned() })
+    }
+
+    fn path_str(&self) -> &str {
+        self.path.as_str()
+    }
+
+    fn with_loc(&self, loc: Loc<'f>, path: WzPath) -> Box<WzNode<'f>> {
+        let mut node = WzNode::new(self.file, loc, path);
+        node.follow_uol = self.follow_uol;
+        Box::new(node)
+    }
+
+    fn mismatch(&self, expected: Dtype) -> WzError {
+        WzError::mismatch(self, expected)
+    }
+
+    /// Get the value of a property, [`None`] for entries of the directory tree.
+    fn prop(&self) -> Option<&Value> {
+        match &self.loc {
+            Loc::Prop(image, i) => Some(&image.props[*i].value),
+            Loc::Entry(_) => None,
+        }
+    }
+
+    /// Get the points of a convex property.
+    fn vex_points(&self) -> Result<&[glam::IVec2]> {
+        match self.prop() {
+            Some(Value::Convex(points)) => Ok(points),
+            _ => Err(self.mismatch(Dtype::VEX)),
+        }
+    }
+
+    /// Get the children of node, parsing the image of an `.img` entry on first access.
+    fn children(&self) -> Result<Option<Children<'f>>> {
+        match &self.loc {
+            Loc::Entry(entry) => match entry.kind {
+                EntryKind::Dir { .. } => {
+                    let file = self.file;
+                    let entries = file.directory.entries(entry, &file.data, &file.key);
+                    Ok(Some(Children::Dir(
+                        entries.ok_or_else(|| WzError::Corrupt { path: self.path_str().to_owned() })?,
+                    )))
+                }
+                EntryKind::Img { offset, size } => {
+                    Ok(Some(Children::Props(self.file.image(offset, size, self.path_str())?, 0)))
+                }
+            },
+            Loc::Prop(image, i) => match image.props[*i].value {
+                Value::Property | Value::Canvas(_) => Ok(Some(Children::Props(image.clone(), *i))),
+                _ => Ok(None),
+            },
+        }
+    }
+
+    /// Open node with given absolute path (e.g. "/Mob/100100.img/info") from the root of its file.
+    fn open_absolute(&self, path: &WzPath) -> Result<Box<WzNode<'f>>> {
+        let mut node = self.with_loc(Loc::Entry(&self.file.directory.root), WzPath::root());
+        for segment in path.segments() {
+            node = node.open_child(segment)?;
+        }
+        Ok(node)
+    }
+
+    /// Open direct child with given name without following UOLs.
+    fn open_child(&self, name: &str) -> Result<Box<WzNode<'f>>> {
+        let child_path = self.path.child(name).ok_or_else(|| WzError::InvalidPath { path: name.to_owned() })?;
+        let loc = match self.children()? {
+            Some(Children::Dir(children)) => children.iter().find(|c| c.name == name).map(Loc::Entry),
+            Some(Children::Props(image, i)) => image.find(i, name).map(|c| Loc::Prop(image, c)),
+            None => None,
+        };
+        match loc {
+            Some(loc) => Ok(self.with_loc(loc, child_path)),
+            None => Err(WzError::NotFound { path: child_path.to_string() }),
+        }
+    }
+
+    /// Follow the chain of UOLs starting at node until a non-UOL node.
+    fn follow(node: Box<WzNode<'f>>) -> Result<Box<WzNode<'f>>> {
+        follow_links(node, WzNode::open_absolute)
+    }
+}
+
+impl Debug for WzNode<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fmt_node(self, f)
+    }
+}
+
+impl<'f> WzNode<'f> {
+    pub fn iter(&self) -> WzNodeIter<'_, 'f> {
+        WzNodeIter::new(self)
+    }
+}
+
+pub struct WzNodeIter<'a, 'f> {
+    base: &'a WzNode<'f>,
+    index: u32,
+}
+
+impl<'a, 'f> WzNodeIter<'a, 'f> {
+    pub fn new(base: &'a WzNode<'f>) -> Self {
+        Self { base, index: 0 }
+    }
+}
+
+impl<'a, 'f> Iterator for WzNodeIter<'a, 'f> {
+    type Item = Box<WzNode<'f>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.base.len() {
+            return None;
+        }
+        let child_node = self.base.child_at(self.index);
+        self.index += 1;
+        child_node.ok()
+    }
+}
+
+/// How the key of a file is chosen among the keys of a [`WzCtx`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyGuess {
+    /// Use the first key decrypting the names of the root directory to plain text.
+    #[default]
+    FirstMatch,
+    /// Try every key and use the one decrypting the most names of the root directory and its subdirectories
+    /// to plain text,
+    /// the earliest one on ties. Slower, but safer when the root directory has few entries.
+    BestMatch,
+    /// Use the first key without checking the names it decrypts.
+    Fixed,
+}
+
+/// Builder of a [`WzCtx`] with chosen keys, e.g.
+/// `WzCtx::builder().regions([Region::Kms, Region::Gms]).guess(KeyGuess::BestMatch).build()`.
+///
+/// Only the `pure` backend can choose keys: the libwz context always tries the keys built into libwz.
+#[derive(Clone, Debug)]
+pub struct WzCtxBuilder {
+    regions: Vec<Region>,
+    user_key: [u8; 32],
+    guess: KeyGuess,
+}
+
+impl Default for WzCtxBuilder {
+    fn default() -> Self {
+        WzCtxBuilder {
+            regions: vec![Region::Gms, Region::Kms, Region::Zero],
+            user_key: USER_KEY,
+            guess: KeyGuess::default(),
+        }
+    }
+}
+
+impl WzCtxBuilder {
+    /// Create a builder trying the keys of GMS, KMS and unencrypted clients, in this order.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only try the key of region.
+    pub fn region(self, region: Region) -> Self {
+        self.regions([region])
+    }
+
+    /// Try the keys of regions, in given order.
+    pub fn regions<I: IntoIterator<Item = Region>>(mut self, regions: I) -> Self {
+        self.regions = regions.into_iter().collect();
+        self
+    }
+
+    /// Only try the key made of a raw IV.
+    pub fn iv(self, iv: [u8; 4]) -> Self {
+        self.region(Region::Custom(iv))
+    }
+
+    /// Use another AES user key, trimmed from a 128 bytes key the way [`USER_KEY`] is.
+    pub fn user_key(mut self, user_key: [u8; 32]) -> Self {
+        self.user_key = user_key;
+        self
+    }
+
+    /// Choose the key of each file with given strategy.
+    pub fn guess(mut self, guess: KeyGuess) -> Self {
+        self.guess = guess;
+        self
+    }
+
+    /// Generate the keys. Fail with [`WzError::ContextInit`] when no region is given.
+    pub fn build(self) -> Result<WzCtx> {
+        if self.regions.is_empty() {
+            return Err(WzError::ContextInit);
+        }
+        let keys = self.regions.iter().map(|&region| Arc::new(WzKey::new(region, self.user_key))).collect();
+        Ok(WzCtx { keys, guess: self.guess })
+    }
+}
+
+/// The keys tried when opening a file.
+pub struct WzCtx {
+    keys: Vec<Arc<WzKey>>,
+    guess: KeyGuess,
+}
+
+impl WzCtx {
+    /// Create a context trying the keys of GMS, KMS and unencrypted clients, in this order.
+    /// See [`WzCtxBuilder`] to choose the keys.
+    pub fn new() -> Result<Self> {
+        WzCtxBuilder::new().build()
+    }
+
+    pub fn builder() -> WzCtxBuilder {
+        WzCtxBuilder::new()
+    }
+
+    /// open wz file with given path, reading it whole into memory.
+    /// The key is chosen by the [`KeyGuess`] of the context,
+    /// and the version is the first one whose hash decrypts every entry offset to an image.
+    /// Unlike libwz, the file does not borrow the context.
+    pub fn open_file(&self, path: &str) -> Result<WzFile> {
+        let data = std::fs::read(path).map_err(|_| WzError::FileNotOpen { path: path.to_owned() })?;
+        WzFile::parse(path, Source::Owned(data), &self.keys, self.guess)
+    }
+
+    /// open wz file held in memory, e.g. downloaded or embedded with `include_bytes!`.
+    /// The bytes are shared, not copied. Errors of the file carry an empty path.
+    pub fn open_bytes(&self, data: Arc<[u8]>) -> Result<WzFile> {
+        WzFile::parse("", Source::Shared(data), &self.keys, self.guess)
+    }
+
+    /// open wz file with given path by mapping it into memory instead of reading it,
+    /// so that only the pages actually visited are loaded.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the returned [`WzFile`] is alive,
+    /// see [`memmap2::Mmap::map`].
+    pub unsafe fn open_mmap(&self, path: &str) -> Result<WzFile> {
+        let not_open = || WzError::FileNotOpen { path: path.to_owned() };
+        let file = File::open(path).map_err(|_| not_open())?;
+        let data = memmap2::Mmap::map(&file).map_err(|_| not_open())?;
+        WzFile::parse(path, Source::Mapped(data), &self.keys, self.guess)
+    }
+
+    /// open wz file read from the start of a reader. Errors of the file carry an empty path.
+    ///
+    /// The reader is not streamed: it is read whole into memory before parsing, like [`WzCtx::open_file`].
+    /// See [`WzCtx::open_mmap`] to avoid loading a large file.
+    pub fn open_reader<R: Read + Seek>(&self, mut reader: R) -> Result<WzFile> {
+        let not_open = |_| WzError::FileNotOpen { path: String::new() };
+        let mut data = vec![];
+        reader.seek(SeekFrom::Start(0)).map_err(not_open)?;
+        reader.read_to_end(&mut data).map_err(not_open)?;
+        WzFile::parse("", Source::Owned(data), &self.keys, self.guess)
+    }
+}
+
+/// A wz file opened by a [`WzCtx`], read into memory or mapped.
+///
+/// The file and its nodes are `Send + Sync`: share the file between threads through an `Arc`
+/// (or a scoped borrow) and run any [`MapleNode`] operation concurrently, with no lock held while
+/// parsing. Two threads visiting the same image or canvas at once may both decode it, and one result is kept.
+pub struct WzFile {
+    data: Source,
+    key: Arc<WzKey>,
+    directory: Directory,
+    images: Mutex<ImageCache>,
+}
+
+impl WzFile {
+    fn parse(path: &str, data: Source, keys: &[Arc<WzKey>], guess: KeyGuess) -> Result<Self> {
+        if directory::start(&data).is_none() {
+            return Err(WzError::FileNotOpen { path: path.to_owned() });
+        }
+        let plausible = |key: &&Arc<WzKey>| {
+            let names = directory::root_names(&data, key);
+            names.is_some_and(|names| names.iter().all(|name| is_plain(name)))
+        };
+        let parse = |key: &Arc<WzKey>| directory::parse(&data, key).map(|directory| (key.clone(), directory));
+        let found = match guess {
+            KeyGuess::FirstMatch => keys.iter().filter(plausible).find_map(parse),
+            KeyGuess::BestMatch => {
+                keys.iter().filter_map(parse).min_by_key(|(_, directory)| Reverse(directory.count_listed(is_plain)))
+            }
+            KeyGuess::Fixed => keys.first().and_then(parse),
+        };
+        let (key, directory) = found.ok_or_else(|| WzError::Decryption { path: path.to_owned() })?;
+        Ok(WzFile { data, key, directory, images: Mutex::new(ImageCache::new()) })
+    }
+
+    /// open root node with given wzfile. The node can not outlive the file.
+    pub fn open_root(&self) -> Result<Box<WzNode<'_>>> {
+        Ok(Box::new(WzNode::new(self, Loc::Entry(&self.directory.root), WzPath::root())))
+    }
+
+    /// Get the region whose key was chosen to decrypt the file.
+    pub fn region(&self) -> Region {
+        self.key.region()
+    }
+
+    /// Get the version of the client which wrote the file.
+    pub fn version(&self) -> u16 {
+        self.directory.version
+    }
+
+    /// Limit the approximate bytes taken by parsed images and their decoded canvases,
+    /// evicting the least recently used images beyond it. [`None`] (the default) keeps every image.
+    /// Evicted images are freed once no node of them is alive, and parsed again when visited.
+    pub fn set_memory_budget(&self, budget: Option<usize>) {
+        self.images().set_budget(budget);
+    }
+
+    /// Get the approximate bytes taken by the parsed images kept by the file.
+    pub fn memory_usage(&self) -> usize {
+        self.images().usage()
+    }
+
+    /// Evict every parsed image, see [`WzNode::unload`].
+    pub fn unload_all(&self) {
+        self.images().clear();
+    }
+
+    /// Lock the image cache. Its LRU state stays valid when a panic poisons the lock, so the poison is ignored.
+    fn images(&self) -> MutexGuard<'_, ImageCache> {
+        self.images.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Get the parsed image at offset, parsing it on first access.
+    fn image(&self, offset: usize, size: usize, path: &str) -> Result<Arc<Image>> {
+        if let Some(image) = self.images().get(offset) {
+            return Ok(image);
+        }
+        // the image can not read past its own end
+        let end = offset.saturating_add(size).min(self.data.len());
+        let image = image::parse(&self.data[..end], offset, &self.key).map_err(|e| match e {
+            ParseError::Header => WzError::Decryption { path: path.to_owned() },
+            ParseError::Corrupt => WzError::Corrupt { path: path.to_owned() },
+        })?;
+        Ok(self.images().insert(offset, image))
+    }
+}
+
+/// Whether a name decrypted with a guessed key looks like plain text.
+fn is_plain(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_graphic() || c == ' ')
+}
+
+impl<'f> MapleNode for WzNode<'f> {
+    type Item = WzNode<'f>;
+
+    fn child<S: AsRef<str>>(&self, path: S) -> Result<Box<Self::Item>> {
+        let mut segments = path.as_ref().split('/').filter(|s| !s.is_empty()).peekable();
+        if segments.peek().is_none() {
+            return Err(WzError::InvalidPath { path: path.as_ref().to_owned() });
+        }
+        let mut node = self.with_loc(self.loc.clone(), self.path.clone());
+        for segment in segments {
+            node = match segment {
+                "." => node,
+                ".." => node.parent()?,
+                name if node.follow_uol => WzNode::follow(node.open_child(name)?)?,
+                name => node.open_child(name)?,
+            };
+        }
+        Ok(node)
+    }
+
+    fn child_at(&self, i: u32) -> Result<Box<Self::Item>> {
+        let out_of_range = || WzError::IndexOutOfRange { path: self.path_str().to_owned(), index: i, len: self.len() };
+        let loc = match self.children()? {
+            Some(Children::Dir(children)) => children.get(i as usize).map(Loc::Entry),
+            Some(Children::Props(image, p)) => {
+                image.props[p].children.get(i as usize).map(|&c| Loc::Prop(image.clone(), c))
+            }
+            None => None,
+        };
+        let mut node = self.with_loc(loc.ok_or_else(out_of_range)?, WzPath::root());
+        let name = node.name()?;
+        node.path = self.path.child(name).ok_or_else(|| WzError::InvalidPath { path: name.to_owned() })?;
+        Ok(node)
+    }
+
+    fn len(&self) -> u32 {
+        match self.children() {
+            Ok(Some(Children::Dir(children))) => children.len() as u32,
+            Ok(Some(Children::Props(image, i))) => image.props[i].children.len() as u32,
+            Ok(None) | Err(_) => 0,
+        }
+    }
+
+    fn path(&self) -> &str {
+        self.path_str()
+    }
+
+    fn parent(&self) -> Result<Box<Self::Item>> {
+        let parent = self.path.parent().ok_or_else(|| WzError::NotFound { path: "/..".to_owned() })?;
+        self.open_absolute(&parent)
+    }
+
+    fn root(&self) -> Result<Box<Self::Item>> {
+        self.open_absolute(&WzPath::root())
+    }
+
+    fn dtype(&self) -> Result<Dtype> {
+        Ok(match self.prop() {
+            None => Dtype::ARY,
+            Some(value) => match value {
+                Value::Nil => Dtype::NIL,
+                Value::Short(_) => Dtype::I16,
+                Value::Int(_) => Dtype::I32,
+                Value::Long(_) => Dtype::I64,
+                Value::Float(_) => Dtype::F32,
+                Value::Double(_) => Dtype::F64,
+                Value::Str(_) => Dtype::STR,
+                Value::Vec(..) => Dtype::VEC,
+                Value::Uol(_) => Dtype::UOL,
+                Value::Canvas(_) => Dtype::IMG,
+                Value::Convex(_) => Dtype::VEX,
+                Value::Sound(_) => Dtype::AO,
+                Value::Property => Dtype::ARY,
+            },
+        })
+    }
+
+    fn int32(&self) -> Result<i32> {
+        match self.prop() {
+            Some(Value::Short(v)) => Ok(*v as i32),
+            Some(Value::Int(v)) => Ok(*v),
+            _ => Err(self.mismatch(Dtype::I32)),
+        }
+    }
+
+    fn int64(&self) -> Result<i64> {
+        match self.prop() {
+            Some(Value::Long(v)) => Ok(*v),
+            _ => Err(self.mismatch(Dtype::I64)),
+        }
+    }
+
+    fn float32(&self) -> Result<f32> {
+        match self.prop() {
+            Some(Value::Float(v)) => Ok(*v),
+            _ => Err(self.mismatch(Dtype::F32)),
+        }
+    }
+
+    fn float64(&self) -> Result<f64> {
+        match self.prop() {
+            Some(Value::Double(v)) => Ok(*v),
+            _ => Err(self.mismatch(Dtype::F64)),
+        }
+    }
+
+    fn str(&self) -> Result<&str> {
+        match self.prop() {
+            Some(Value::Str(s)) => Ok(s),
+            _ => Err(self.mismatch(Dtype::STR)),
+        }
+    }
+
+    fn uol(&self) -> Result<&str> {
+        match self.prop() {
+            Some(Value::Uol(s)) => Ok(s),
+            _ => Err(self.mismatch(Dtype::UOL)),
+        }
+    }
+
+    fn resolve(&self) -> Result<Box<Self::Item>> {
+        self.uol()?;
+        WzNode::follow(self.with_loc(self.loc.clone(), self.path.clone()))
+    }
+
+    fn name(&self) -> Result<&str> {
+        Ok(match &self.loc {
+            Loc::Entry(entry) => &entry.name,
+            Loc::Prop(image, i) => &image.props[*i].name,
+        })
+    }
+
+    fn vex_len(&self) -> Result<u32> {
+        Ok(self.vex_points()?.len() as u32)
+    }
+
+    fn vex_at(&self, i: u32) -> Result<glam::IVec2> {
+        let points = self.vex_points()?;
+        let len = points.len() as u32;
+        points.get(i as usize).copied().ok_or_else(|| WzError::IndexOutOfRange {
+            path: self.path_str().to_owned(),
+            index: i,
+            len,
+        })
+    }
+
+    fn vex(&self) -> Result<Vec<glam::IVec2>> {
+        Ok(self.vex_points()?.to_vec())
+    }
+
+    fn vec(&self) -> Result<glam::Vec2> {
+        match self.prop() {
+            Some(Value::Vec(x, y)) => Ok(glam::Vec2::new(*x as f32, *y as f32)),
+            _ => Err(self.mismatch(Dtype::VEC)),
+        }
+    }
+
+    fn img(&self) -> Result<ImageBuffer<'_>> {
+        let (image, index, canvas) = match &self.loc {
+            Loc::Prop(image, i) => match &image.props[*i].value {
+                Value::Canvas(canvas) => (image, *i, canvas),
+                _ => return Err(self.mismatch(Dtype::IMG)),
+            },
+            Loc::Entry(_) => return Err(self.mismatch(Dtype::IMG)),
+        };
+        let pixels = match canvas.pixels.get() {
+            Some(pixels) => pixels,
+            None => {
+                let path = || self.path_str().to_owned();
+                let compressed = self.payload(canvas.offset, canvas.len)?;
+                let decoded =
+                    canvas::decode(compressed, &self.file.key, canvas.width, canvas.height, canvas.depth, canvas.scale)
+                        .map_err(|e| match e {
+                            CanvasError::Decompression => WzError::Decompression { path: path() },
+                            CanvasError::UnsupportedFormat(format) => {
+                                WzError::UnsupportedFormat { path: path(), format }
+                            }
+                            CanvasError::TooLarge => WzError::Corrupt { path: path() },
+                        })?;
+                let pixels = image.decoded(index, decoded);
+                self.file.images().resize(image.offset);
+                pixels
+            }
+        };
+        Ok(ImageBuffer {
+            data: Cow::Borrowed(pixels),
+            width: canvas.width,
+            height: canvas.height,
+            depth: canvas.depth,
+            scale: canvas.scale,
+        })
+    }
+
+    fn img_info(&self) -> Result<ImageInfo> {
+        match self.prop() {
+            Some(Value::Canvas(canvas)) => {
+                Ok(ImageInfo { width: canvas.width, height: canvas.height, depth: canvas.depth, scale: canvas.scale })
+            }
+            _ => Err(self.mismatch(Dtype::IMG)),
+        }
+    }
+
+    fn audio(&self) -> Result<AudioBuffer<'_>> {
+        let sound = match self.prop() {
+            Some(Value::Sound(sound)) => sound,
+            _ => return Err(self.mismatch(Dtype::AO)),
+        };
+        let data = self.payload(sound.offset, sound.len)?;
+        let format = sound.wave_format.map_or(0, |wave_format| wave_format.codec.tag());
+        Ok(AudioBuffer {
+            data: Cow::Borrowed(data),
+            size: sound.len as u32,
+            ms: sound.ms,
+            format,
+            wave_format: sound.wave_format,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::Codec;
+
+    #[test]
+    fn open_bytes() {
+        let file = fixture::open();
+        assert_eq!((file.version(), file.region()), (fixture::VERSION, Region::Gms));
+        let root = file.open_root().unwrap();
+        let names: Vec<String> = root.iter().map(|node| node.name_owned().unwrap()).collect();
+        assert_eq!(names, ["Mob", "Test.img"]);
+        assert_eq!(root.child("Mob/Mob.img/hp").int32(), Ok(10));
+        assert_eq!(root.child("Test.img").len(), 14);
+    }
+
+    #[test]
+    fn regions() {
+        for region in [Region::Gms, Region::Kms, Region::Zero] {
+            let file = WzCtx::new().unwrap().open_bytes(Arc::from(fixture::bytes(region))).unwrap();
+            assert_eq!(file.region(), region);
+            assert_eq!(file.open_root().child("Test.img/str").str_owned(), Ok("héllo".to_owned()));
+        }
+        let kms = WzCtx::builder().region(Region::Kms).build().unwrap();
+        let gms = kms.open_bytes(Arc::from(fixture::bytes(Region::Gms)));
+        assert_eq!(gms.err(), Some(WzError::Decryption { path: String::new() }));
+        let truncated = WzCtx::new().unwrap().open_bytes(Arc::from(&b"PKG1"[..]));
+        assert_eq!(truncated.err(), Some(WzError::FileNotOpen { path: String::new() }));
+    }
+
+    /// Get the path and value of every node of file, depth first.
+    fn contents(file: &WzFile) -> Vec<(String, Result<crate::value::WzValue>)> {
+        file.open_root().unwrap().walk_dfs().map(|node| (node.path().to_owned(), node.value())).collect()
+    }
+
+    #[test]
+    fn sources() {
+        let bytes = fixture::bytes(Region::Gms);
+        let ctx = WzCtx::new().unwrap();
+        let expected = contents(&ctx.open_bytes(Arc::from(&bytes[..])).unwrap());
+        assert!(expected.len() > 20);
+
+        // the reader is read from its start, wherever it is
+        let mut cursor = std::io::Cursor::new(bytes.clone());
+        cursor.set_position(bytes.len() as u64);
+        assert_eq!(contents(&ctx.open_reader(cursor).unwrap()), expected);
+
+        let path = std::env::temp_dir().join(format!("wz-pure-{}.wz", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        let path_str = path.to_str().unwrap();
+        let mapped = unsafe { ctx.open_mmap(path_str) }.unwrap();
+        assert_eq!(contents(&mapped), expected);
+        assert_eq!(contents(&ctx.open_file(path_str).unwrap()), expected);
+        drop(mapped);
+        std::fs::remove_file(&path).unwrap();
+        let missing = unsafe { ctx.open_mmap(path_str) }.err();
+        assert_eq!(missing, Some(WzError::FileNotOpen { path: path_str.to_owned() }));
+    }
+
+    #[test]
+    fn keys() {
+        let iv = [0x01, 0x02, 0x03, 0x04];
+        let custom = || Arc::from(fixture::bytes(Region::Custom(iv)));
+        let best = WzCtx::builder()
+            .regions([Region::Gms, Region::Kms, Region::Custom(iv), Region::Zero])
+            .guess(KeyGuess::BestMatch)
+            .build()
+            .unwrap();
+        let file = best.open_bytes(custom()).unwrap();
+        assert_eq!(file.region(), Region::Custom(iv));
+        assert_eq!(file.open_root().child("Test.img/str").str_owned(), Ok("héllo".to_owned()));
+        let iv_only = WzCtx::builder().iv(iv).build().unwrap().open_bytes(custom()).unwrap();
+        assert_eq!(iv_only.open_root().child("Test.img/int").int32(), Ok(100_000));
+
+        let fixed = WzCtx::builder().region(Region::Gms).guess(KeyGuess::Fixed).build().unwrap();
+        // the names are not checked, so the file opens, but its images fail to decrypt;
+        // with this IV the garbled name of Test.img has no slash, so the image can be reached by index
+        let wrong = fixed.open_bytes(custom()).unwrap();
+        assert_eq!(wrong.region(), Region::Gms);
+        let image = wrong.open_root().child_at(1).child_at(0);
+        assert!(matches!(image, Err(WzError::Decryption { .. })), "{:?}", image);
+
+        let mut user_key = USER_KEY;
+        user_key[0] = 0x42;
+        let bytes = || Arc::from(fixture::bytes_with_user_key(Region::Gms, user_key));
+        let ctx = WzCtx::builder().user_key(user_key).build().unwrap();
+        let file = ctx.open_bytes(bytes()).unwrap();
+        assert_eq!(file.region(), Region::Gms);
+        assert_eq!(file.open_root().child("Test.img/str").str_owned(), Ok("héllo".to_owned()));
+        assert_eq!(WzCtx::new().unwrap().open_bytes(bytes()).err(), Some(WzError::Decryption { path: String::new() }));
+    }
+
+    #[test]
+    fn values() {
+        let file = fixture::open();
+        let test = file.open_root().child("Test.img");
+        assert_eq!(test.child("short").int32(), Ok(-7));
+        assert_eq!(test.child("int").int32(), Ok(100_000));
+        assert_eq!(test.child("long").int64(), Ok(1 << 40));
+        assert_eq!(test.child("float").float32(), Ok(1.5));
+        assert_eq!(test.child("double").float64(), Ok(2.25));
+        assert_eq!(test.child("number").as_i32(), Ok(42));
+        assert_eq!(test.child("vec").vec(), Ok(glam::Vec2::new(3.0, -4.0)));
+        assert_eq!(test.child("convex").vex(), Ok(vec![glam::IVec2::new(1, 2), glam::IVec2::new(300, -400)]));
+        assert_eq!(test.child("link").uol(), Ok("sub/x"));
+        assert_eq!(test.child("link").resolve().int32(), Ok(42));
+        assert_eq!(test.child("sub/../sub/./x").path(), "/Test.img/sub/x");
+        assert_eq!(test.child("missing").err(), Some(WzError::NotFound { path: "/Test.img/missing".to_owned() }));
+        let mismatch =
+            WzError::TypeMismatch { path: "/Test.img/str".to_owned(), expected: Dtype::I32, actual: Some(Dtype::STR) };
+        assert_eq!(test.child("str").int32(), Err(mismatch));
+    }
+
+    #[test]
+    fn corrupt_canvas_length() {
+        let bytes = fixture::bytes(Region::Gms);
+        let pixels = fixture::zlib(&(0..16).collect::<Vec<u8>>());
+        // the pixels of Test.img/canvas, after those of Mob.img/icon
+        let at = bytes.windows(pixels.len()).rposition(|window| window == &pixels[..]).unwrap() - 5;
+        for len in [i32::MIN, i32::MAX, -1] {
+            let mut corrupt = bytes.clone();
+            corrupt[at..at + 4].copy_from_slice(&len.to_le_bytes());
+            let file = WzCtx::new().unwrap().open_bytes(Arc::from(corrupt)).unwrap();
+            let err = file.open_root().child("Test.img/int").err();
+            assert!(matches!(err, Some(WzError::Corrupt { .. })), "{}: {:?}", len, err);
+        }
+    }
+
+    #[test]
+    fn links() {
+        let file = fixture::open();
+        let mut root = file.open_root().unwrap();
+        let links = root.child("Mob/Mob.img/links").unwrap();
+        let up = links.child("up").unwrap();
+        assert_eq!((up.dtype(), up.uol()), (Ok(Dtype::UOL), Ok("../hp")));
+        let hp = up.resolve().unwrap();
+        assert_eq!((hp.path(), hp.int32()), ("/Mob/Mob.img/hp", Ok(10)));
+        let broken = WzError::BrokenLink { path: "/Mob/Mob.img/links/dangling".to_owned(), link: "nothing".to_owned() };
+        assert_eq!(links.child("dangling").uol(), Ok("nothing"));
+        assert_eq!(links.child("dangling").resolve().err(), Some(broken.clone()));
+        let cyclic = WzError::CyclicLink { path: "/Mob/Mob.img/links/a".to_owned() };
+        assert_eq!(links.child("a").resolve().err(), Some(cyclic.clone()));
+        assert_eq!(links.child("b").uol(), Ok("a"));
+
+        // without following, child stops at the UOL nodes themselves
+        assert!(!root.follows_uol());
+        assert_eq!(root.child("Mob/Mob.img/links/up").dtype(), Ok(Dtype::UOL));
+        assert_eq!(root.child("Mob/Mob.img/links/a").dtype(), Ok(Dtype::UOL));
+        root.set_follow_uol(true);
+        let hp = root.child("Mob/Mob.img/links/up").unwrap();
+        assert_eq!((hp.path(), hp.int32()), ("/Mob/Mob.img/hp", Ok(10)));
+        assert!(hp.follows_uol());
+        assert_eq!(root.child("Test.img/link").int32(), Ok(42));
+        assert_eq!(root.child("Mob/Mob.img/links/dangling").err(), Some(broken));
+        assert_eq!(root.child("Mob/Mob.img/links/a").err(), Some(cyclic));
+    }
+
+    #[test]
+    fn payloads() {
+        let file = fixture::open();
+        let test = file.open_root().child("Test.img");
+        let canvas = test.child("canvas").unwrap();
+        assert_eq!(canvas.dtype(), Ok(Dtype::IMG));
+        assert_eq!(canvas.img_info(), Ok(ImageInfo { width: 2, height: 2, depth: 2, scale: 0 }));
+        assert_eq!(canvas.img().unwrap().data[..], (0..16).collect::<Vec<u8>>()[..]);
+        assert_eq!(canvas.child("origin").vec(), Ok(glam::Vec2::new(5.0, 6.0)));
+        let sound = test.child("sound").unwrap();
+        let audio = sound.audio().unwrap();
+        assert_eq!((&audio.data[..], audio.ms, audio.codec()), (&b"audio"[..], 1234, Codec::Mp3));
+        assert_eq!(sound.raw_audio(), Ok(&b"audio"[..]));
+        let pcm = test.child("pcm").unwrap();
+        let format = pcm.audio().unwrap().wave_format.unwrap();
+        assert_eq!((format.codec, format.channels, format.sample_rate), (Codec::Pcm, 2, 8000));
+    }
+
+    /// Check that the stored payloads of the fixture canvas and sound are borrowed from `data` without a copy.
+    fn assert_borrowed(file: &WzFile, data: &[u8]) {
+        let test = file.open_root().child("Test.img");
+        let raw = test.child("canvas").unwrap().raw_img().unwrap();
+        assert_eq!(raw, &fixture::zlib(&(0..16).collect::<Vec<u8>>())[..]);
+        let audio = test.child("sound").unwrap().raw_audio().unwrap();
+        assert_eq!(audio, b"audio");
+        let range = data.as_ptr_range();
+        for raw in [raw, audio] {
+            let borrowed = raw.as_ptr_range();
+            assert!(range.start <= borrowed.start && borrowed.end <= range.end);
+        }
+    }
+
+    #[test]
+    fn borrowed() {
+        let bytes: Arc<[u8]> = Arc::from(fixture::bytes(Region::Gms));
+        let ctx = WzCtx::new().unwrap();
+        assert_borrowed(&ctx.open_bytes(bytes.clone()).unwrap(), &bytes);
+
+        let path = std::env::temp_dir().join(format!("wz-borrowed-{}.wz", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        let mapped = unsafe { ctx.open_mmap(path.to_str().unwrap()) }.unwrap();
+        assert!(matches!(mapped.data, Source::Mapped(_)));
+        assert_borrowed(&mapped, &mapped.data);
+        drop(mapped);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn _assert<T: Send + Sync>() {}
+
+    #[test]
+    fn threads() {
+        _assert::<WzFile>();
+        _assert::<WzNode<'_>>();
+        let file = fixture::open();
+        let root = file.open_root().unwrap();
+        let (int, hp) = std::thread::scope(|scope| {
+            let int = scope.spawn(|| root.child("Test.img/int").int32());
+            let hp = scope.spawn(|| root.child("Mob/Mob.img/hp").int32());
+            (int.join().unwrap(), hp.join().unwrap())
+        });
+        assert_eq!((int, hp), (Ok(100_000), Ok(10)));
+    }
+
+    #[test]
+    fn debug() {
+        let file = fixture::open();
+        let test = file.open_root().child("Test.img");
+        assert_eq!(
+            format!("{:?}", test.child("canvas")),
+            "Ok(WzNode Path[/Test.img/canvas] Type[IMG] Value[dim:2x2,child num=9])"
+        );
+        assert_eq!(format!("{:?}", test.child("int").unwrap()), "WzNode Path[/Test.img/int] Type[I32] Value[100000]");
+        // printing a canvas does not decode it
+        let before = file.memory_usage();
+        let _ = format!("{:?}", test.child("canvas"));
+        assert_eq!(file.memory_usage(), before);
+    }
+
+    #[test]
+    fn unload() {
+        let file = fixture::open();
+        assert_eq!(file.memory_usage(), 0);
+        let canvas = file.open_root().child("Test.img/canvas").unwrap();
+        let parsed = file.memory_usage();
+        assert!(parsed > 0);
+        canvas.img().unwrap();
+        assert_eq!(file.memory_usage(), parsed + 16);
+        canvas.unload();
+        assert_eq!(file.memory_usage(), 0);
+        // the node keeps its image alive
+        assert_eq!(canvas.img().unwrap().data.len(), 16);
+        file.open_root().child("Test.img/int").unwrap();
+        assert_eq!(file.memory_usage(), parsed);
+        file.unload_all();
+        assert_eq!(file.memory_usage(), 0);
+    }
+
+    #[test]
+    fn poisoned_cache() {
+        let file = fixture::open();
+        let poison = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _cache = file.images.lock().unwrap();
+            panic!("poison the image cache");
+        }));
+        assert!(poison.is_err() && file.images.is_poisoned());
+        assert_eq!(file.open_root().child("Test.img/int").int32(), Ok(100_000));
+        assert!(file.memory_usage() > 0);
+        file.unload_all();
+        assert_eq!(file.memory_usage(), 0);
+    }
+
+    #[test]
+    fn memory_budget() {
+        let file = fixture::open();
+        let root = file.open_root().unwrap();
+        root.child("Test.img/int").unwrap();
+        let test = file.memory_usage();
+        file.unload_all();
+        file.set_memory_budget(Some(1));
+        root.child("Test.img/int").unwrap();
+        root.child("Mob/Mob.img/hp").unwrap();
+        let mob = file.memory_usage();
+        assert!(mob < test);
+        root.child("Test.img/int").unwrap();
+        assert_eq!(file.memory_usage(), test);
+        file.set_memory_budget(None);
+        root.child("Mob/Mob.img/hp").unwrap();
+        assert_eq!(file.memory_usage(), test + mob);
+    }
+
+    #[test]
+    fn least_recently_used() {
+        let file = fixture::open();
+        let root = file.open_root().unwrap();
+        root.child("Test.img/int").unwrap();
+        root.child("Mob/Mob.img/hp").unwrap();
+        let both = file.memory_usage();
+        file.set_memory_budget(Some(both));
+        // the decoded pixels exceed the budget, evicting Mob.img which was used less recently
+        root.child("Test.img/canvas").unwrap().img().unwrap();
+        let test = file.memory_usage();
+        file.set_memory_budget(None);
+        root.child("Mob/Mob.img/hp").unwrap();
+        assert!(file.memory_usage() > test);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::pure::crypto::WzKey;
+use std::convert::TryInto;
+
+/// Offsets of entries are encrypted with this constant and the version hash.
+const OFFSET_CONSTANT: u32 = 0x581C_3F6D;
+
+/// A little endian cursor over the bytes of a wz file.
+/// Every read returns [`None`] when it would run past the end of data.
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8], pos: usize) -> Self {
+        Reader { data, pos }
+    }
+
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn seek(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    pub(crate) fn skip(&mut self, n: usize) -> Option<()> {
+        self.bytes(n).map(|_| ())
+    }
+
+    pub(crate) fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(n)?;
+        let bytes = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.bytes(N)?.try_into().ok()
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        Some(self.array::<1>()?[0])
+    }
+
+    pub(crate) fn i8(&mut self) -> Option<i8> {
+        Some(self.u8()? as i8)
+    }
+
+    pub(crate) fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn i16(&mut self) -> Option<i16> {
+        Some(i16::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn i64(&mut self) -> Option<i64> {
+        Some(i64::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn f64(&mut self) -> Option<f64> {
+        Some(f64::from_le_bytes(self.array()?))
+    }
+
+    /// An i8, or an i32 following the marker -128.
+    pub(crate) fn compressed_i32(&mut self) -> Option<i32> {
+        match self.i8()? {
+            -128 => self.i32(),
+            n => Some(n as i32),
+        }
+    }
+
+    /// An i8, or an i64 following the marker -128.
+    pub(crate) fn compressed_i64(&mut self) -> Option<i64> {
+        match self.i8()? {
+            -128 => self.i64(),
+            n => Some(n as i64),
+        }
+    }
+
+    /// An encrypted string: a positive length for utf16, a negative one for latin1.
+    pub(crate) fn string(&mut self, key: &WzKey) -> Option<String> {
+        let len = self.i8()?;
+        match len {
+            0 => Some(String::new()),
+            1..=127 => {
+                let len = if len == 127 { self.i32()? } else { len as i32 };
+                let len: usize = len.try_into().ok()?;
+                let bytes = self.bytes(len.checked_mul(2)?)?;
+                let stream = key.stream(bytes.len());
+                let mut mask: u16 = 0xAAAA;
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .zip(stream.chunks_exact(2))
+                    .map(|(c, k)| {
+                        let unit = u16::from_le_bytes([c[0] ^ k[0], c[1] ^ k[1]]) ^ mask;
+                        mask = mask.wrapping_add(1);
+                        unit
+                    })
+                    .collect();
+                String::from_utf16(&units).ok()
+            }
+            _ => {
+                let len = if len == -128 { self.i32()? } else { -(len as i32) };
+                let len: usize = len.try_into().ok()?;
+                let bytes = self.bytes(len)?;
+                let stream = key.stream(len);
+                let mut mask: u8 = 0xAA;
+                let chars = bytes.iter().zip(stream.iter()).map(|(c, k)| {
+                    let c = (c ^ k ^ mask) as char;
+                    mask = mask.wrapping_add(1);
+                    c
+                });
+                Some(chars.collect())
+            }
+        }
+    }
+
+    /// An encrypted string at given position, leaving the cursor untouched.
+    pub(crate) fn string_at(&mut self, pos: usize, key: &WzKey) -> Option<String> {
+        let saved = self.pos;
+        self.pos = pos;
+        let s = self.string(key);
+        self.pos = saved;
+        s
+    }
+
+    /// A string inline or referenced by an offset relative to `base`, the start of the image.
+    pub(crate) fn string_block(&mut self, base: usize, key: &WzKey) -> Option<String> {
+        match self.u8()? {
+            0x00 | 0x73 => self.string(key),
+            0x01 | 0x1B => {
+                let offset = self.i32()?;
+                self.string_at(base.checked_add(offset.try_into().ok()?)?, key)
+            }
+            _ => None,
+        }
+    }
+
+    /// An entry offset, encrypted with its own position, the start of data and the version hash.
+    pub(crate) fn offset(&mut self, start: u32, hash: u32) -> Option<usize> {
+        let pos = self.pos as u32;
+        let mut offset = (pos.wrapping_sub(start)) ^ u32::MAX;
+        offset = offset.wrapping_mul(hash);
+        offset = offset.wrapping_sub(OFFSET_CONSTANT);
+        offset = offset.rotate_left(offset & 0x1F);
+        offset ^= self.u32()?;
+        offset = offset.wrapping_add(start.wrapping_mul(2));
+        Some(offset as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pure::{
+        crypto::{Region, USER_KEY},
+        fixture::{encrypt_offset, Writer},
+    };
+
+    #[test]
+    fn ascii_string() {
+        let key = WzKey::new(Region::Zero, USER_KEY);
+        let data = [0xFD, b'a' ^ 0xAA, b'b' ^ 0xAB, b'c' ^ 0xAC, 0x00];
+        let mut reader = Reader::new(&data, 0);
+        assert_eq!(reader.string(&key).as_deref(), Some("abc"));
+        assert_eq!(reader.string(&key).as_deref(), Some(""));
+        assert_eq!(reader.pos(), data.len());
+    }
+
+    #[test]
+    fn utf16_string() {
+        let key = WzKey::new(Region::Zero, USER_KEY);
+        // 'é' is 0x00E9, masked with 0xAAAA
+        let data = [0x01, 0x43, 0xAA];
+        assert_eq!(Reader::new(&data, 0).string(&key).as_deref(), Some("é"));
+    }
+
+    #[test]
+    fn encrypted_strings() {
+        let key = WzKey::new(Region::Kms, USER_KEY);
+        let mut writer = Writer::new(Region::Kms);
+        writer.string("Mob.img");
+        writer.string("héllo");
+        writer.string(&"x".repeat(200));
+        let mut reader = Reader::new(&writer.buf, 0);
+        assert_eq!(reader.string(&key).as_deref(), Some("Mob.img"));
+        assert_eq!(reader.string(&key).as_deref(), Some("héllo"));
+        assert_eq!(reader.string(&key), Some("x".repeat(200)));
+        assert_eq!(reader.string(&key), None);
+    }
+
+    #[test]
+    fn string_truncated() {
+        let key = WzKey::new(Region::Zero, USER_KEY);
+        assert_eq!(Reader::new(&[0xFD, b'a'], 0).string(&key), None);
+        assert_eq!(Reader::new(&[0x80, 0xFF, 0xFF, 0xFF, 0x7F], 0).string(&key), None);
+    }
+
+    #[test]
+    fn offset() {
+        let (start, hash) = (60, 1876);
+        let mut data = vec![0; 64];
+        data.extend_from_slice(&0xCDF7_D081u32.to_le_bytes());
+        assert_eq!(Reader::new(&data, 64).offset(start, hash), Some(1000));
+        for (pos, target) in [(64, 62), (100, 1 << 20), (4096, 4100)] {
+            let mut data = vec![0; pos as usize];
+            data.extend_from_slice(&encrypt_offset(pos, start, hash, target).to_le_bytes());
+            assert_eq!(Reader::new(&data, pos as usize).offset(start, hash), Some(target as usize));
+        }
+    }
+}
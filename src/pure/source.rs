@@ -0,0 +1,24 @@
+use memmap2::Mmap;
+use std::{ops::Deref, sync::Arc};
+
+/// The bytes of an opened wz file.
+pub(crate) enum Source {
+    /// Read from a path or a reader.
+    Owned(Vec<u8>),
+    /// Handed over by the caller, possibly shared with other files.
+    Shared(Arc<[u8]>),
+    /// Mapped from a path.
+    Mapped(Mmap),
+}
+
+impl Deref for Source {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Source::Owned(data) => data,
+            Source::Shared(data) => data,
+            Source::Mapped(data) => data,
+        }
+    }
+}
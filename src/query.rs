@@ -0,0 +1,405 @@
+use crate::{
+    error::{Result, WzError},
+    node::{Dtype, MapleNode},
+};
+use std::{cmp::Ordering, collections::HashSet, ops::Range};
+
+/// A query over the descendants of a node, made by [`MapleNode::select`].
+///
+/// A query is a slash separated list of steps, each one matching children of the nodes matched so far:
+///
+/// * `name` matches the child with that name, `*` and `?` are wildcards for any run of characters
+///   and any single character, e.g. `*.img` or `0?`. `.` and `..` are rejected, a query never leaves the node.
+/// * `**` matches the node itself and all its descendants, e.g. `**/info` finds every `info` below.
+/// * Brackets after a name narrow the step down:
+///   * `[3]`, `[1..4]`, `[1..=3]`, `[..4]` or `[2..]` keep children whose index among all the children
+///     of their parent is in range.
+///   * `[dtype=IMG]` or `[dtype!=ARY]` compare the [`Dtype`](crate::node::Dtype) of the child, ignoring case.
+///   * `[info/level>50]` compares the node at a path from the child, as a number when both sides are
+///     numbers (with [`MapleNode::as_f64`]) and as text when the node is a [`Dtype::STR`](crate::node::Dtype::STR)
+///     or [`Dtype::UOL`](crate::node::Dtype::UOL); other nodes never match. Operators are `=`, `!=`, `<`, `<=`,
+///     `>` and `>=`. `[info/level]` alone checks that the node exists.
+///
+/// E.g. `root.select("*/info[level>=50]/maxHP")` on the root of Mob.wz finds the max HP of every
+/// mob from level 50. The node the query runs on is never yielded itself, and each node at most once.
+pub struct Select<N> {
+    steps: Vec<Step>,
+    pending: Vec<(Box<N>, usize)>,
+    /// Paths yielded so far, kept only when more than one `**` may reach a node twice.
+    seen: Option<HashSet<String>>,
+}
+
+enum Step {
+    /// `**`
+    Descendants,
+    Child {
+        pattern: String,
+        index: Option<Range<u32>>,
+        predicates: Vec<Predicate>,
+    },
+}
+
+struct Predicate {
+    /// Path below the node, or `dtype`.
+    path: String,
+    compare: Option<(Op, String)>,
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl<N: MapleNode<Item = N>> Select<N> {
+    pub(crate) fn new<M: MapleNode<Item = N> + ?Sized>(node: &M, query: &str) -> Result<Self> {
+        let steps = parse(query)?;
+        let descendants = steps.iter().filter(|step| matches!(step, Step::Descendants)).count();
+        let seen = if descendants > 1 { Some(HashSet::new()) } else { None };
+        let mut select = Select { steps, pending: vec![], seen };
+        select.start(node, 0);
+        Ok(select)
+    }
+
+    /// Push the states reached from the node the query runs on, which is itself never yielded.
+    fn start<M: MapleNode<Item = N> + ?Sized>(&mut self, node: &M, step: usize) {
+        match self.steps.get(step) {
+            None => {}
+            Some(Step::Descendants) => {
+                let children: Vec<_> = (0..node.len()).filter_map(|i| node.child_at(i).ok()).collect();
+                self.pending.extend(children.into_iter().rev().map(|child| (child, step)));
+                self.start(node, step + 1);
+            }
+            Some(Step::Child { pattern, index, predicates }) => {
+                let matched = children(node, pattern, index, predicates);
+                self.pending.extend(matched.into_iter().rev().map(|child| (child, step + 1)));
+            }
+        }
+    }
+}
+
+impl<N: MapleNode<Item = N>> Iterator for Select<N> {
+    type Item = Box<N>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, step)) = self.pending.pop() {
+            match self.steps.get(step) {
+                None => {
+                    if self.seen.as_mut().map_or(true, |seen| seen.insert(node.path().to_owned())) {
+                        return Some(node);
+                    }
+                }
+                Some(Step::Descendants) => {
+                    let children: Vec<_> = (0..node.len()).filter_map(|i| node.child_at(i).ok()).collect();
+                    self.pending.extend(children.into_iter().rev().map(|child| (child, step)));
+                    self.pending.push((node, step + 1));
+                }
+                Some(Step::Child { pattern, index, predicates }) => {
+                    let matched = children(&*node, pattern, index, predicates);
+                    self.pending.extend(matched.into_iter().rev().map(|child| (child, step + 1)));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Open the children of node matching a step, in order.
+fn children<N: MapleNode<Item = N>, M: MapleNode<Item = N> + ?Sized>(
+    node: &M,
+    pattern: &str,
+    index: &Option<Range<u32>>,
+    predicates: &[Predicate],
+) -> Vec<Box<N>> {
+    let accept = |child: &N| predicates.iter().all(|predicate| predicate.test(child));
+    if index.is_none() && !pattern.contains(['*', '?']) {
+        return node.child(pattern).into_iter().filter(|child| accept(child)).collect();
+    }
+    let range = index.clone().unwrap_or(0..u32::MAX);
+    (range.start..range.end.min(node.len()))
+        .filter_map(|i| node.child_at(i).ok())
+        .filter(|child| child.name().is_ok_and(|name| glob(pattern, name)) && accept(child))
+        .collect()
+}
+
+impl Predicate {
+    fn test<N: MapleNode<Item = N>>(&self, node: &N) -> bool {
+        if self.path == "dtype" {
+            let dtype = match node.dtype() {
+                Ok(dtype) => dtype.to_str(),
+                Err(_) => return false,
+            };
+            return match &self.compare {
+                Some((op, value)) => op.test(dtype.cmp(&*value.to_uppercase())),
+                None => true,
+            };
+        }
+        let target = match node.child(&self.path) {
+            Ok(target) => target,
+            Err(_) => return false,
+        };
+        let (op, value) = match &self.compare {
+            Some((op, value)) => (*op, value),
+            None => return true,
+        };
+        // as_f64 rejects I64, which it can not hold losslessly, so longs are compared as i64 where possible
+        let long = target.dtype().ok() == Some(Dtype::I64);
+        if long {
+            if let (Ok(left), Ok(right)) = (target.as_i64(), value.parse::<i64>()) {
+                return op.test(left.cmp(&right));
+            }
+        }
+        let left = if long { target.as_i64().map(|left| left as f64) } else { target.as_f64() };
+        if let (Ok(left), Ok(right)) = (left, value.parse::<f64>()) {
+            return left.partial_cmp(&right).is_some_and(|ordering| op.test(ordering));
+        }
+        // only read text, which never decodes a payload
+        let left = match target.dtype() {
+            Ok(Dtype::STR) => target.str(),
+            Ok(Dtype::UOL) => target.uol(),
+            _ => return false,
+        };
+        left.is_ok_and(|left| op.test(left.cmp(value)))
+    }
+}
+
+impl Op {
+    fn test(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// Match name against a pattern with wildcards `*` and `?`.
+fn glob(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` in pattern and of name when it was met, to backtrack to
+    let mut star = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn parse(query: &str) -> Result<Vec<Step>> {
+    let invalid = |reason: &str| WzError::InvalidQuery { query: query.to_owned(), reason: reason.to_owned() };
+    let mut steps = vec![];
+    for segment in split_steps(query).ok_or_else(|| invalid("unbalanced brackets"))? {
+        if segment.is_empty() {
+            continue;
+        }
+        if segment == "**" {
+            steps.push(Step::Descendants);
+            continue;
+        }
+        let (pattern, mut rest) = segment.split_at(segment.find('[').unwrap_or(segment.len()));
+        if pattern.is_empty() {
+            return Err(invalid("brackets must follow a name or wildcard"));
+        }
+        if matches!(pattern, "." | "..") {
+            return Err(invalid("`.` and `..` steps leave the node"));
+        }
+        let (mut index, mut predicates) = (None, vec![]);
+        while let Some(inner) = rest.strip_prefix('[') {
+            let end = inner.find(']').ok_or_else(|| invalid("unbalanced brackets"))?;
+            let content = inner[..end].trim();
+            rest = &inner[end + 1..];
+            if let Some(range) = parse_range(content) {
+                if index.is_some() {
+                    return Err(invalid("more than one index range in a step"));
+                }
+                index = Some(range);
+            } else {
+                predicates.push(parse_predicate(content).ok_or_else(|| invalid("bad predicate"))?);
+            }
+        }
+        if !rest.is_empty() {
+            return Err(invalid("text after brackets"));
+        }
+        steps.push(Step::Child { pattern: pattern.to_owned(), index, predicates });
+    }
+    if steps.is_empty() {
+        return Err(invalid("empty query"));
+    }
+    Ok(steps)
+}
+
+/// Split a query on the slashes outside of brackets, [`None`] when brackets are unbalanced.
+fn split_steps(query: &str) -> Option<Vec<&str>> {
+    let (mut steps, mut depth, mut start) = (vec![], 0u32, 0);
+    for (i, c) in query.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.checked_sub(1)?,
+            '/' if depth == 0 => {
+                steps.push(&query[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+    steps.push(&query[start..]);
+    Some(steps)
+}
+
+fn parse_range(content: &str) -> Option<Range<u32>> {
+    let bound = |s: &str| -> Option<Option<u32>> {
+        match s.trim() {
+            "" => Some(None),
+            s => s.parse().ok().map(Some),
+        }
+    };
+    if let Some((start, end)) = content.split_once("..=") {
+        let end = bound(end)??;
+        return Some(bound(start)?.unwrap_or(0)..end.checked_add(1)?);
+    }
+    if let Some((start, end)) = content.split_once("..") {
+        return Some(bound(start)?.unwrap_or(0)..bound(end)?.unwrap_or(u32::MAX));
+    }
+    let index: u32 = content.parse().ok()?;
+    Some(index..index.checked_add(1)?)
+}
+
+fn parse_predicate(content: &str) -> Option<Predicate> {
+    // longest operators first, so that `<=` is not read as `<`
+    const OPS: [(&str, Op); 6] =
+        [("!=", Op::Ne), ("<=", Op::Le), (">=", Op::Ge), ("=", Op::Eq), ("<", Op::Lt), (">", Op::Gt)];
+    let found = OPS
+        .iter()
+        .filter_map(|&(token, op)| content.find(token).map(|at| (at, token, op)))
+        .min_by_key(|&(at, token, _)| (at, std::cmp::Reverse(token.len())));
+    let (path, compare) = match found {
+        Some((at, token, op)) => (&content[..at], Some((op, content[at + token.len()..].trim().to_owned()))),
+        None => (content, None),
+    };
+    let path = path.trim().trim_matches('/');
+    if path.is_empty() || path == "dtype" && !matches!(compare, None | Some((Op::Eq | Op::Ne, _))) {
+        return None;
+    }
+    Some(Predicate { path: path.to_owned(), compare })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(glob("*.img", "100100.img"));
+        assert!(glob("*.img", ".img"));
+        assert!(!glob("*.img", "100100.img.bak"));
+        assert!(glob("0?", "01"));
+        assert!(!glob("0?", "0"));
+        assert!(!glob("0?", "012"));
+        assert!(glob("*a*b", "xaxxab"));
+        assert!(glob("**", ""));
+        assert!(glob("info", "info"));
+        assert!(!glob("info", "Info"));
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("3"), Some(3..4));
+        assert_eq!(parse_range("1..4"), Some(1..4));
+        assert_eq!(parse_range("1..=3"), Some(1..4));
+        assert_eq!(parse_range("..4"), Some(0..4));
+        assert_eq!(parse_range("2.."), Some(2..u32::MAX));
+        assert_eq!(parse_range(" 1 .. 2 "), Some(1..2));
+        assert_eq!(parse_range("..=4294967295"), None);
+        assert_eq!(parse_range("-1"), None);
+        assert_eq!(parse_range("level"), None);
+    }
+
+    #[test]
+    fn predicates() {
+        // the path, whether the operator accepts Less, and the value
+        let parse = |content: &str| {
+            let predicate = parse_predicate(content)?;
+            Some((predicate.path, predicate.compare.map(|(op, value)| (op.test(Ordering::Less), value))))
+        };
+        assert_eq!(parse("info/level"), Some(("info/level".to_owned(), None)));
+        assert_eq!(parse("level>=50"), Some(("level".to_owned(), Some((false, "50".to_owned())))));
+        assert_eq!(parse("level <= 50"), Some(("level".to_owned(), Some((true, "50".to_owned())))));
+        assert_eq!(parse("name!=a=b"), Some(("name".to_owned(), Some((true, "a=b".to_owned())))));
+        assert_eq!(parse("/info/"), Some(("info".to_owned(), None)));
+        assert_eq!(parse("dtype=img"), Some(("dtype".to_owned(), Some((false, "img".to_owned())))));
+        assert_eq!(parse("dtype<IMG"), None);
+        assert_eq!(parse("=50"), None);
+    }
+
+    #[test]
+    fn steps() {
+        let steps = parse("**/info[1..3][level>50]").unwrap();
+        assert!(matches!(steps[0], Step::Descendants));
+        match &steps[1] {
+            Step::Child { pattern, index, predicates } => {
+                assert_eq!(pattern, "info");
+                assert_eq!(index, &Some(1..3));
+                assert_eq!(predicates.len(), 1);
+            }
+            Step::Descendants => panic!("expected a child step"),
+        }
+        for query in ["", "/", "[1]", "a[1][2]", "a[1", "a]", "a[1]b", "../x", "a/./b", "a/.."] {
+            assert!(matches!(parse(query), Err(WzError::InvalidQuery { .. })), "{}", query);
+        }
+    }
+
+    #[cfg(feature = "pure")]
+    #[test]
+    fn compare_text() {
+        let file = crate::pure::fixture::open();
+        let root = file.open_root().unwrap();
+        let paths = |query| root.select(query).unwrap().map(|node| node.path().to_owned()).collect::<Vec<_>>();
+        assert_eq!(paths("*[str=héllo]"), ["/Test.img"]);
+        assert_eq!(paths("*[link=sub/x]"), ["/Test.img"]);
+        assert_eq!(paths("*[number>=42]"), ["/Test.img"]);
+        let usage = file.memory_usage();
+        assert!(paths("*[canvas=x]").is_empty());
+        assert_eq!(file.memory_usage(), usage);
+    }
+
+    #[cfg(feature = "pure")]
+    #[test]
+    fn compare_numbers() {
+        let file = crate::pure::fixture::open();
+        let root = file.open_root().unwrap();
+        let paths = |query| root.select(query).unwrap().map(|node| node.path().to_owned()).collect::<Vec<_>>();
+        assert_eq!(paths("*[short<0]"), ["/Test.img"]);
+        assert_eq!(paths("*[double>2]"), ["/Test.img"]);
+        assert_eq!(paths("*[long>1]"), ["/Test.img"]);
+        assert_eq!(paths("*[long=1099511627776]"), ["/Test.img"]);
+        assert!(paths("*[long=1099511627777]").is_empty());
+        assert_eq!(paths("*[long<1.1e12]"), ["/Test.img"]);
+    }
+}
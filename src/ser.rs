@@ -0,0 +1,310 @@
+//! Serialization of whole subtrees with feature `serde`, see [`Serializable`].
+//!
+//! Nodes are opened as a `Result<Box<_>>`, which does not implement `Serialize` as
+//! [`WzError`](crate::error::WzError) does not, so unwrap them with `?` before serializing:
+//!
+//! ```
+//! use serde::Serialize;
+//! use wz::{
+//!     node::MapleNode,
+//!     ser::{BinaryMode, Serializable},
+//! };
+//!
+//! fn dump<N>(root: &N, mut writer: impl std::io::Write) -> Result<(), Box<dyn std::error::Error>>
+//! where
+//!     N: MapleNode,
+//!     N::Item: MapleNode<Item = N::Item> + Serialize,
+//! {
+//!     // `serde_json::to_writer(&mut writer, &root.child("Mob"))` does not compile without the `?`
+//!     serde_json::to_writer(&mut writer, &root.child("Mob")?)?;
+//!     let node = root.child("Mob/100100.img")?;
+//!     serde_json::to_writer(&mut writer, &Serializable::new(&*node, &BinaryMode::Base64))?;
+//!     Ok(())
+//! }
+//! ```
+
+use crate::node::{Dtype, MapleNode};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{
+    ser::{Error, SerializeMap, SerializeSeq},
+    Serialize, Serializer,
+};
+use std::{
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+};
+
+/// How the binary payload of [`Dtype::IMG`] and [`Dtype::AO`] nodes is serialized.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum BinaryMode {
+    /// Only the metadata (dimensions, duration, format) is serialized.
+    #[default]
+    Omit,
+    /// The payload is serialized as a base64 string in field `data`.
+    Base64,
+    /// The payload is written to a file under the directory, mirroring the node path with extension
+    /// `.bgra` or `.audio` appended, and its location is serialized in field `file`.
+    /// Names which are not a plain file name, such as `..`, fail the serialization.
+    External(PathBuf),
+}
+
+/// Serialize a whole subtree of node with given [`BinaryMode`].
+///
+/// Containers ([`Dtype::ARY`] and [`Dtype::IMG`]) become maps from child names to children,
+/// numbers and strings become plain values, [`Dtype::VEC`] becomes `{"x", "y"}`,
+/// [`Dtype::VEX`] a list of them and [`Dtype::UOL`] `{"_uol": link}`.
+/// The pixels of a canvas are stored in key `_image` next to its children,
+/// the data of audio in key `_audio`.
+pub struct Serializable<'a, N> {
+    node: &'a N,
+    binary: &'a BinaryMode,
+}
+
+impl<'a, N: MapleNode<Item = N>> Serializable<'a, N> {
+    pub fn new(node: &'a N, binary: &'a BinaryMode) -> Self {
+        Serializable { node, binary }
+    }
+
+    /// Convert the payload according to the binary mode.
+    fn payload(&self, data: &[u8], extension: &str) -> std::io::Result<(Option<String>, Option<String>)> {
+        match self.binary {
+            BinaryMode::Omit => Ok((None, None)),
+            BinaryMode::Base64 => Ok((Some(STANDARD.encode(data)), None)),
+            BinaryMode::External(dir) => {
+                let file = external_file(dir, self.node.path(), extension)?;
+                if let Some(parent) = file.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::write(&file, data)?;
+                Ok((None, Some(file.to_string_lossy().into_owned())))
+            }
+        }
+    }
+
+    fn serialize_children<S: Serializer>(&self, map: &mut S::SerializeMap) -> Result<(), S::Error> {
+        for i in 0..self.node.len() {
+            let child = self.node.child_at(i).map_err(S::Error::custom)?;
+            let name = child.name().map_err(S::Error::custom)?;
+            map.serialize_entry(name, &Serializable::new(&*child, self.binary))?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the file under dir mirroring the path of a node, with extension appended to its name.
+/// Fail unless every name of the path is a single plain file name, so that nothing is written outside dir.
+fn external_file(dir: &Path, path: &str, extension: &str) -> std::io::Result<PathBuf> {
+    let mut file = dir.to_path_buf();
+    let names: Vec<&str> = path.strip_prefix('/').unwrap_or(path).split('/').collect();
+    for (i, name) in names.iter().enumerate() {
+        let mut components = Path::new(name).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(normal)), None) if normal == *name => {}
+            _ => {
+                let reason = format!("node [{}] has a name unfit for a file: [{}]", path, name);
+                return Err(std::io::Error::new(ErrorKind::InvalidInput, reason));
+            }
+        }
+        if i + 1 == names.len() {
+            file.push(format!("{}.{}", name, extension));
+        } else {
+            file.push(name);
+        }
+    }
+    Ok(file)
+}
+
+#[derive(Serialize)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Serialize)]
+struct Image {
+    width: u32,
+    height: u32,
+    depth: u16,
+    scale: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Audio {
+    size: u32,
+    ms: u32,
+    format: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+}
+
+impl<N: MapleNode<Item = N>> Serialize for Serializable<'_, N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let node = self.node;
+        match node.dtype().map_err(S::Error::custom)? {
+            Dtype::NIL | Dtype::UNK => serializer.serialize_unit(),
+            Dtype::I16 | Dtype::I32 => serializer.serialize_i32(node.int32().map_err(S::Error::custom)?),
+            Dtype::I64 => serializer.serialize_i64(node.int64().map_err(S::Error::custom)?),
+            Dtype::F32 => serializer.serialize_f32(node.float32().map_err(S::Error::custom)?),
+            Dtype::F64 => serializer.serialize_f64(node.float64().map_err(S::Error::custom)?),
+            Dtype::STR => serializer.serialize_str(node.str().map_err(S::Error::custom)?),
+            Dtype::VEC => {
+                let v = node.vec().map_err(S::Error::custom)?;
+                Point { x: v.x as i32, y: v.y as i32 }.serialize(serializer)
+            }
+            Dtype::VEX => {
+                let points = node.vex().map_err(S::Error::custom)?;
+                let mut seq = serializer.serialize_seq(Some(points.len()))?;
+                for p in points {
+                    seq.serialize_element(&Point { x: p.x, y: p.y })?;
+                }
+                seq.end()
+            }
+            Dtype::UOL => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("_uol", node.uol().map_err(S::Error::custom)?)?;
+                map.end()
+            }
+            Dtype::ARY => {
+                let mut map = serializer.serialize_map(Some(node.len() as usize))?;
+                self.serialize_children::<S>(&mut map)?;
+                map.end()
+            }
+            Dtype::IMG => {
+                // the pixels are only decoded when they are serialized
+                let info = node.img_info().map_err(S::Error::custom)?;
+                let (data, file) = match self.binary {
+                    BinaryMode::Omit => (None, None),
+                    _ => {
+                        let img = node.img().map_err(S::Error::custom)?;
+                        self.payload(&img.data, "bgra").map_err(S::Error::custom)?
+                    }
+                };
+                let image =
+                    Image { width: info.width, height: info.height, depth: info.depth, scale: info.scale, data, file };
+                let mut map = serializer.serialize_map(Some(node.len() as usize + 1))?;
+                map.serialize_entry("_image", &image)?;
+                self.serialize_children::<S>(&mut map)?;
+                map.end()
+            }
+            Dtype::AO => {
+                let ao = node.audio().map_err(S::Error::custom)?;
+                let (data, file) = self.payload(&ao.data, "audio").map_err(S::Error::custom)?;
+                let audio = Audio { size: ao.size, ms: ao.ms, format: ao.format, data, file };
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("_audio", &audio)?;
+                map.end()
+            }
+        }
+    }
+}
+
+/// Serialize the subtree of node with [`BinaryMode::Omit`].
+#[cfg(feature = "libwz")]
+impl Serialize for crate::wz::WzNode<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Serializable::new(self, &BinaryMode::Omit).serialize(serializer)
+    }
+}
+
+/// Serialize the subtree of node with [`BinaryMode::Omit`].
+#[cfg(feature = "pure")]
+impl Serialize for crate::pure::WzNode<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Serializable::new(self, &BinaryMode::Omit).serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn external_file_appends_extension() {
+        let file = external_file(Path::new("out"), "/Mob/100100.img/stand/1.5", "bgra").unwrap();
+        assert_eq!(file, Path::new("out/Mob/100100.img/stand/1.5.bgra"));
+    }
+
+    #[test]
+    fn external_file_stays_under_dir() {
+        for path in ["/a/../b", "/a/./b", "/..", "/a//b", "/", "/a/"] {
+            assert!(external_file(Path::new("out"), path, "audio").is_err(), "{}", path);
+        }
+    }
+
+    #[cfg(feature = "pure")]
+    fn fixture_json(binary: &BinaryMode) -> serde_json::Value {
+        let file = crate::pure::fixture::open();
+        let test = file.open_root().child("Test.img").unwrap();
+        serde_json::to_value(Serializable::new(&*test, binary)).unwrap()
+    }
+
+    #[cfg(feature = "pure")]
+    #[test]
+    fn omit() {
+        let file = crate::pure::fixture::open();
+        let json = serde_json::to_value(file.open_root().child("Test.img").unwrap()).unwrap();
+        assert_eq!(json, fixture_json(&BinaryMode::Omit));
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "short": -7,
+                "int": 100_000,
+                "long": 1i64 << 40,
+                "float": 1.5,
+                "double": 2.25,
+                "str": "héllo",
+                "number": "42",
+                "vec": {"x": 3, "y": -4},
+                "convex": [{"x": 1, "y": 2}, {"x": 300, "y": -400}],
+                "sub": {"x": 42},
+                "link": {"_uol": "sub/x"},
+                "canvas": {
+                    "_image": {"width": 2, "height": 2, "depth": 2, "scale": 0},
+                    "origin": {"x": 5, "y": 6},
+                    "head": {"x": 1, "y": -20},
+                    "lt": {"x": -3, "y": -4},
+                    "rb": {"x": 3, "y": 0},
+                    "map": {"navel": {"x": 0, "y": -10}, "hand": {"x": 7, "y": -8}},
+                    "delay": 120,
+                    "z": "armOverHair",
+                    "a0": 0,
+                    "a1": 240,
+                },
+                "sound": {"_audio": {"size": 5, "ms": 1234, "format": 0x55}},
+                "pcm": {"_audio": {"size": 8, "ms": 1, "format": 1}},
+            })
+        );
+    }
+
+    #[cfg(feature = "pure")]
+    #[test]
+    fn base64() {
+        let json = fixture_json(&BinaryMode::Base64);
+        assert_eq!(json["canvas"]["_image"]["data"], STANDARD.encode((0..16).collect::<Vec<u8>>()));
+        assert_eq!(json["canvas"]["origin"], serde_json::json!({"x": 5, "y": 6}));
+        assert_eq!(json["sound"]["_audio"]["data"], STANDARD.encode(b"audio"));
+        assert_eq!(json["pcm"]["_audio"]["data"], STANDARD.encode([1, 0, 2, 0, 3, 0, 4, 0]));
+        assert_eq!(json["link"], serde_json::json!({"_uol": "sub/x"}));
+    }
+
+    #[cfg(feature = "pure")]
+    #[test]
+    fn external() {
+        let dir = std::env::temp_dir().join(format!("wz-ser-{}", std::process::id()));
+        let json = fixture_json(&BinaryMode::External(dir.clone()));
+        let canvas = dir.join("Test.img/canvas.bgra");
+        let sound = dir.join("Test.img/sound.audio");
+        assert_eq!(json["canvas"]["_image"]["file"], canvas.to_string_lossy().as_ref());
+        assert_eq!(json["canvas"]["_image"].get("data"), None);
+        assert_eq!(json["sound"]["_audio"]["file"], sound.to_string_lossy().as_ref());
+        assert_eq!(std::fs::read(&canvas).unwrap(), (0..16).collect::<Vec<u8>>());
+        assert_eq!(std::fs::read(&sound).unwrap(), b"audio");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,313 @@
+use crate::{
+    error::{Result, WzError},
+    node::AudioBuffer,
+};
+use std::{convert::TryInto, path::Path};
+
+/// The codec of a sound, from the `wFormatTag` of its [`WaveFormat`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// Uncompressed samples, `WAVE_FORMAT_PCM` (1).
+    Pcm,
+    /// MPEG layer 3 frames, `WAVE_FORMAT_MPEGLAYER3` (0x55), as most sounds are.
+    Mp3,
+    /// Any other tag, 0 for a sound without header.
+    Other(u16),
+}
+
+impl Codec {
+    pub fn from_tag(tag: u16) -> Codec {
+        match tag {
+            1 => Codec::Pcm,
+            0x55 => Codec::Mp3,
+            tag => Codec::Other(tag),
+        }
+    }
+
+    pub fn tag(self) -> u16 {
+        match self {
+            Codec::Pcm => 1,
+            Codec::Mp3 => 0x55,
+            Codec::Other(tag) => tag,
+        }
+    }
+}
+
+/// The WAVEFORMATEX header preceding the data of a sound.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WaveFormat {
+    pub codec: Codec,
+    pub channels: u16,
+    pub sample_rate: u32,
+    /// The average bytes per second, `nAvgBytesPerSec`.
+    pub byte_rate: u32,
+    /// The bytes of one sample of every channel, `nBlockAlign`.
+    pub block_align: u16,
+    /// The bits of one sample, 0 for compressed codecs.
+    pub bits_per_sample: u16,
+}
+
+impl WaveFormat {
+    /// Parse the first 16 bytes of a WAVEFORMATEX, the extra bytes of a codec are ignored.
+    /// Return [`None`] when it is shorter.
+    pub fn parse(header: &[u8]) -> Option<WaveFormat> {
+        if header.len() < 16 {
+            return None;
+        }
+        let u16_at = |at: usize| u16::from_le_bytes([header[at], header[at + 1]]);
+        let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap_or_default());
+        Some(WaveFormat {
+            codec: Codec::from_tag(u16_at(0)),
+            channels: u16_at(2),
+            sample_rate: u32_at(4),
+            byte_rate: u32_at(8),
+            block_align: u16_at(12),
+            bits_per_sample: u16_at(14),
+        })
+    }
+}
+
+impl AudioBuffer<'_> {
+    /// Get the codec of the sound, from [`AudioBuffer::format`].
+    /// A sound without header whose data starts with an ID3 tag or an MPEG frame is [`Codec::Mp3`].
+    pub fn codec(&self) -> Codec {
+        match Codec::from_tag(self.format) {
+            Codec::Other(0) if self.data.starts_with(b"ID3") || is_mpeg_sync(&self.data) => Codec::Mp3,
+            codec => codec,
+        }
+    }
+
+    /// Get a playable WAV file of the sound.
+    ///
+    /// [`Codec::Pcm`] samples are copied as is, [`Codec::Mp3`] is decoded with feature `mp3`,
+    /// see `AudioBuffer::decode_mp3`. Fail with [`WzError::UnsupportedFormat`] for other codecs,
+    /// PCM without [`AudioBuffer::wave_format`] and MP3 without the feature, with an empty path.
+    pub fn to_wav(&self) -> Result<Vec<u8>> {
+        match (self.codec(), self.wave_format) {
+            (Codec::Pcm, Some(format)) => Ok(wav(&format, &self.data)),
+            #[cfg(feature = "mp3")]
+            (Codec::Mp3, _) => Ok(self.decode_mp3()?.to_wav()),
+            (codec, _) => Err(WzError::UnsupportedFormat { path: String::new(), format: codec.tag() as u32 }),
+        }
+    }
+
+    /// Save the sound as a WAV file, see [`AudioBuffer::to_wav`].
+    pub fn save_wav<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        save(path.as_ref(), &self.to_wav()?)
+    }
+
+    /// Save the data of a [`Codec::Mp3`] sound as is, which is a playable MP3 file.
+    /// Fail with [`WzError::UnsupportedFormat`] with an empty path for other codecs.
+    pub fn save_mp3<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        match self.codec() {
+            Codec::Mp3 => save(path.as_ref(), &self.data),
+            codec => Err(WzError::UnsupportedFormat { path: String::new(), format: codec.tag() as u32 }),
+        }
+    }
+}
+
+/// Interleaved 16 bit samples decoded by [`AudioBuffer::decode_mp3`].
+#[cfg(feature = "mp3")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Samples {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub data: Vec<i16>,
+}
+
+#[cfg(feature = "mp3")]
+impl Samples {
+    /// Get a WAV file of the samples.
+    pub fn to_wav(&self) -> Vec<u8> {
+        let format = WaveFormat {
+            codec: Codec::Pcm,
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            byte_rate: self.sample_rate * self.channels as u32 * 2,
+            block_align: self.channels * 2,
+            bits_per_sample: 16,
+        };
+        let data: Vec<u8> = self.data.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        wav(&format, &data)
+    }
+
+    /// Save the samples as a WAV file.
+    pub fn save_wav<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        save(path.as_ref(), &self.to_wav())
+    }
+}
+
+#[cfg(feature = "mp3")]
+impl AudioBuffer<'_> {
+    /// Decode the MPEG frames of a [`Codec::Mp3`] sound, skipping frames which fail to decode.
+    /// Fail with [`WzError::Decode`] with an empty path when the stream can not be read or no frame decodes.
+    pub fn decode_mp3(&self) -> Result<Samples> {
+        use std::io::{Cursor, ErrorKind};
+        use symphonia::{
+            core::{
+                audio::SampleBuffer,
+                codecs::{Decoder, DecoderOptions},
+                errors::Error,
+                formats::{FormatOptions, FormatReader},
+                io::MediaSourceStream,
+            },
+            default::{codecs::MpaDecoder, formats::MpaReader},
+        };
+        let failed = |reason: String| WzError::Decode { path: String::new(), reason };
+        let source = MediaSourceStream::new(Box::new(Cursor::new(self.data.to_vec())), Default::default());
+        let mut reader = MpaReader::try_new(source, &FormatOptions::default()).map_err(|e| failed(e.to_string()))?;
+        let open = |reader: &MpaReader| {
+            let track = reader.default_track().ok_or_else(|| failed("no MPEG track".to_owned()))?;
+            MpaDecoder::try_new(&track.codec_params, &DecoderOptions::default()).map_err(|e| failed(e.to_string()))
+        };
+        let mut decoder = open(&reader)?;
+        let mut samples = Samples::default();
+        loop {
+            let packet = match reader.next_packet() {
+                Ok(packet) => packet,
+                Err(Error::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => break,
+                // the parameters of the stream changed, which a new decoder picks up
+                Err(Error::ResetRequired) => {
+                    decoder = open(&reader)?;
+                    continue;
+                }
+                Err(e) => return Err(failed(e.to_string())),
+            };
+            let decoded = match decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(Error::DecodeError(_)) => continue,
+                Err(Error::ResetRequired) => {
+                    decoder.reset();
+                    continue;
+                }
+                Err(e) => return Err(failed(e.to_string())),
+            };
+            let spec = *decoded.spec();
+            let mut buffer = SampleBuffer::<i16>::new(decoded.capacity() as u64, spec);
+            buffer.copy_interleaved_ref(decoded);
+            samples.channels = spec.channels.count() as u16;
+            samples.sample_rate = spec.rate;
+            samples.data.extend_from_slice(buffer.samples());
+        }
+        if samples.channels == 0 {
+            return Err(failed("no MPEG frame decoded".to_owned()));
+        }
+        Ok(samples)
+    }
+}
+
+/// Split a WAV file into its format and the range of its samples, [`None`] when data is not one.
+#[cfg(any(feature = "libwz", test))]
+pub(crate) fn split_wav(data: &[u8]) -> Option<(WaveFormat, std::ops::Range<usize>)> {
+    if data.get(..4)? != b"RIFF" || data.get(8..12)? != b"WAVE" {
+        return None;
+    }
+    let (mut at, mut format) = (12, None);
+    while let Some(header) = data.get(at..at + 8) {
+        let len = u32::from_le_bytes(header[4..].try_into().ok()?) as usize;
+        let start = at + 8;
+        let end = start.checked_add(len)?;
+        match &header[..4] {
+            b"fmt " => format = WaveFormat::parse(data.get(start..end)?),
+            b"data" => return Some((format?, start..end.min(data.len()))),
+            _ => {}
+        }
+        at = end + len % 2;
+    }
+    None
+}
+
+/// Whether data starts with the 11 bit sync of an MPEG frame.
+fn is_mpeg_sync(data: &[u8]) -> bool {
+    data.len() >= 2 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0
+}
+
+/// Write a WAV file of PCM samples in format.
+fn wav(format: &WaveFormat, data: &[u8]) -> Vec<u8> {
+    let mut wav = Vec::with_capacity(44 + data.len() + 1);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data.len() as u32 + data.len() as u32 % 2).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&Codec::Pcm.tag().to_le_bytes());
+    wav.extend_from_slice(&format.channels.to_le_bytes());
+    wav.extend_from_slice(&format.sample_rate.to_le_bytes());
+    wav.extend_from_slice(&format.byte_rate.to_le_bytes());
+    wav.extend_from_slice(&format.block_align.to_le_bytes());
+    wav.extend_from_slice(&format.bits_per_sample.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    wav.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        wav.push(0);
+    }
+    wav
+}
+
+fn save(path: &Path, data: &[u8]) -> Result<()> {
+    std::fs::write(path, data).map_err(|e| WzError::Export { path: path.display().to_string(), reason: e.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PCM: WaveFormat = WaveFormat {
+        codec: Codec::Pcm,
+        channels: 2,
+        sample_rate: 8000,
+        byte_rate: 32000,
+        block_align: 4,
+        bits_per_sample: 16,
+    };
+
+    #[test]
+    fn wav_round_trip() {
+        let wav = wav(&PCM, &[1, 0, 2, 0, 3]);
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(split_wav(&wav), Some((PCM, 44..49)));
+        // a chunk before the format is skipped, with its padding
+        let mut list = wav[..12].to_vec();
+        list.extend_from_slice(b"LIST\x01\0\0\0x\0");
+        list.extend_from_slice(&wav[12..]);
+        assert_eq!(split_wav(&list), Some((PCM, 54..59)));
+        assert_eq!(split_wav(&wav[..40]), None);
+        assert_eq!(split_wav(b"audio"), None);
+    }
+
+    #[cfg(feature = "pure")]
+    #[test]
+    fn fixture_sounds() {
+        use crate::{node::MapleNode, pure::fixture};
+        let file = fixture::open();
+        let test = file.open_root().child("Test.img").unwrap();
+        let pcm = test.child("pcm").unwrap();
+        let pcm = pcm.audio().unwrap();
+        assert_eq!((pcm.codec(), pcm.wave_format), (Codec::Pcm, Some(PCM)));
+        let wav = pcm.to_wav().unwrap();
+        assert_eq!(split_wav(&wav), Some((PCM, 44..52)));
+        assert_eq!(wav[44..], [1, 0, 2, 0, 3, 0, 4, 0]);
+
+        let sound = test.child("sound").unwrap();
+        let sound = sound.audio().unwrap();
+        assert_eq!((sound.codec(), sound.wave_format.map(|format| format.codec)), (Codec::Mp3, Some(Codec::Mp3)));
+        let path = std::env::temp_dir().join(format!("wz-sound-{}.mp3", std::process::id()));
+        sound.save_mp3(&path).unwrap();
+        let saved = std::fs::read(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved.unwrap(), b"audio");
+        let unsupported = WzError::UnsupportedFormat { path: String::new(), format: 1 };
+        assert_eq!(pcm.save_mp3(&path), Err(unsupported));
+        assert!(!path.exists());
+    }
+
+    #[cfg(feature = "mp3")]
+    #[test]
+    fn mp3_errors_keep_reason() {
+        let audio = AudioBuffer { data: b"audio"[..].into(), size: 5, ms: 0, format: 0x55, wave_format: None };
+        match audio.decode_mp3() {
+            Err(WzError::Decode { path, reason }) => assert!(path.is_empty() && !reason.is_empty()),
+            other => panic!("expected a decode error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use crate::node::{AudioBuffer, Dtype, ImageBuffer};
+use std::{
+    convert::TryFrom,
+    fmt::{Display, Formatter},
+};
+
+/// An owned copy of the payload of a node, which may outlive the file.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WzValue {
+    /// Payload of [`Dtype::NIL`] (and [`Dtype::UNK`])
+    Nil,
+    /// Payload of [`Dtype::I16`] or [`Dtype::I32`]
+    Int(i32),
+    /// Payload of [`Dtype::I64`]
+    Long(i64),
+    /// Payload of [`Dtype::F32`]
+    Float(f32),
+    /// Payload of [`Dtype::F64`]
+    Double(f64),
+    /// Payload of [`Dtype::VEC`]
+    Vec(glam::Vec2),
+    /// Payload of [`Dtype::STR`]
+    Str(String),
+    /// Link of [`Dtype::UOL`]
+    Uol(String),
+    /// Pixels of [`Dtype::IMG`]
+    Canvas(ImageBuffer<'static>),
+    /// Points of [`Dtype::VEX`]
+    Convex(Vec<glam::IVec2>),
+    /// Data of [`Dtype::AO`]
+    Audio(AudioBuffer<'static>),
+    /// [`Dtype::ARY`], which has children only
+    Property,
+}
+
+impl WzValue {
+    /// Get the [`Dtype`] of node holding this value.
+    /// [`WzValue::Int`] is reported as [`Dtype::I32`] even when read from [`Dtype::I16`].
+    pub fn dtype(&self) -> Dtype {
+        match self {
+            WzValue::Nil => Dtype::NIL,
+            WzValue::Int(_) => Dtype::I32,
+            WzValue::Long(_) => Dtype::I64,
+            WzValue::Float(_) => Dtype::F32,
+            WzValue::Double(_) => Dtype::F64,
+            WzValue::Vec(_) => Dtype::VEC,
+            WzValue::Str(_) => Dtype::STR,
+            WzValue::Uol(_) => Dtype::UOL,
+            WzValue::Canvas(_) => Dtype::IMG,
+            WzValue::Convex(_) => Dtype::VEX,
+            WzValue::Audio(_) => Dtype::AO,
+            WzValue::Property => Dtype::ARY,
+        }
+    }
+}
+
+impl Display for WzValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WzValue::Nil => write!(f, "nil"),
+            WzValue::Int(v) => write!(f, "{}", v),
+            WzValue::Long(v) => write!(f, "{}", v),
+            WzValue::Float(v) => write!(f, "{}", v),
+            WzValue::Double(v) => write!(f, "{}", v),
+            WzValue::Vec(v) => write!(f, "vec({})", v),
+            WzValue::Str(s) => write!(f, "{}", s),
+            WzValue::Uol(s) => write!(f, "uol({})", s),
+            WzValue::Canvas(p) => write!(f, "dim:{}x{}", p.width, p.height),
+            WzValue::Convex(v) => {
+                let points: Vec<String> = v.iter().map(|p| p.to_string()).collect();
+                write!(f, "vex({})", points.join(", "))
+            }
+            WzValue::Audio(a) => write!(f, "size:{},ms:{},format:{}", a.size, a.ms, a.format),
+            WzValue::Property => write!(f, "property"),
+        }
+    }
+}
+
+impl TryFrom<WzValue> for i32 {
+    type Error = WzValue;
+
+    fn try_from(value: WzValue) -> Result<Self, Self::Error> {
+        match value {
+            WzValue::Int(v) => Ok(v),
+            _ => Err(value),
+        }
+    }
+}
+
+impl TryFrom<WzValue> for i64 {
+    type Error = WzValue;
+
+    fn try_from(value: WzValue) -> Result<Self, Self::Error> {
+        match value {
+            WzValue::Int(v) => Ok(v as i64),
+            WzValue::Long(v) => Ok(v),
+            _ => Err(value),
+        }
+    }
+}
+
+impl TryFrom<WzValue> for f32 {
+    type Error = WzValue;
+
+    fn try_from(value: WzValue) -> Result<Self, Self::Error> {
+        match value {
+            WzValue::Float(v) => Ok(v),
+            _ => Err(value),
+        }
+    }
+}
+
+impl TryFrom<WzValue> for f64 {
+    type Error = WzValue;
+
+    fn try_from(value: WzValue) -> Result<Self, Self::Error> {
+        match value {
+            WzValue::Int(v) => Ok(v as f64),
+            WzValue::Float(v) => Ok(v as f64),
+            WzValue::Double(v) => Ok(v),
+            _ => Err(value),
+        }
+    }
+}
+
+impl TryFrom<WzValue> for String {
+    type Error = WzValue;
+
+    fn try_from(value: WzValue) -> Result<Self, Self::Error> {
+        match value {
+            WzValue::Str(s) | WzValue::Uol(s) => Ok(s),
+            _ => Err(value),
+        }
+    }
+}
+
+impl TryFrom<WzValue> for glam::Vec2 {
+    type Error = WzValue;
+
+    fn try_from(value: WzValue) -> Result<Self, Self::Error> {
+        match value {
+            WzValue::Vec(v) => Ok(v),
+            _ => Err(value),
+        }
+    }
+}
+
+impl TryFrom<WzValue> for Vec<glam::IVec2> {
+    type Error = WzValue;
+
+    fn try_from(value: WzValue) -> Result<Self, Self::Error> {
+        match value {
+            WzValue::Convex(v) => Ok(v),
+            _ => Err(value),
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use crate::node::{Dtype, MapleNode};
+use std::collections::VecDeque;
+
+type Filter<'a, N> = Box<dyn FnMut(&N) -> bool + 'a>;
+
+/// A recursive walk over the descendants of a node, made by [`MapleNode::walk_dfs`] or [`MapleNode::walk_bfs`].
+///
+/// The node the walk starts at is not yielded, its children are at depth 1.
+/// Children which can not be opened are skipped, as in `WzNodeIter`.
+pub struct Walk<'a, N> {
+    pending: VecDeque<(Box<N>, u32)>,
+    breadth_first: bool,
+    max_depth: Option<u32>,
+    descend_canvas: bool,
+    filter: Option<Filter<'a, N>>,
+}
+
+impl<'a, N: MapleNode<Item = N>> Walk<'a, N> {
+    pub(crate) fn new<M: MapleNode<Item = N> + ?Sized>(node: &M, breadth_first: bool) -> Self {
+        let pending = (0..node.len()).filter_map(|i| node.child_at(i).ok()).map(|child| (child, 1)).collect();
+        Walk { pending, breadth_first, max_depth: None, descend_canvas: true, filter: None }
+    }
+
+    /// Do not yield nodes deeper than `depth`, e.g. 1 for the children only.
+    pub fn max_depth(mut self, depth: u32) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Only yield the nodes `filter` accepts, pruning the subtree of every rejected node.
+    /// Unlike [`Iterator::filter`], the descendants of a rejected node are not visited at all.
+    pub fn filter_tree<F: FnMut(&N) -> bool + 'a>(mut self, filter: F) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Whether to descend into the children of [`Dtype::IMG`] nodes (e.g. `origin`), true by default.
+    pub fn descend_canvas(mut self, descend: bool) -> Self {
+        self.descend_canvas = descend;
+        self
+    }
+}
+
+impl<N: MapleNode<Item = N>> Iterator for Walk<'_, N> {
+    type Item = Box<N>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, depth) = self.pending.pop_front()?;
+            if self.max_depth.is_some_and(|max| depth > max) {
+                continue;
+            }
+            if let Some(filter) = &mut self.filter {
+                if !filter(&node) {
+                    continue;
+                }
+            }
+            let descend = self.descend_canvas || node.dtype().ok() != Some(Dtype::IMG);
+            if descend && self.max_depth.map_or(true, |max| depth < max) {
+                let children = (0..node.len()).filter_map(|i| node.child_at(i).ok()).map(|child| (child, depth + 1));
+                if self.breadth_first {
+                    self.pending.extend(children);
+                } else {
+                    let children: Vec<_> = children.collect();
+                    for child in children.into_iter().rev() {
+                        self.pending.push_front(child);
+                    }
+                }
+            }
+            return Some(node);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "pure"))]
+mod tests {
+    use crate::{node::MapleNode, pure::fixture};
+
+    const TEST: [&str; 14] = [
+        "short", "int", "long", "float", "double", "str", "number", "vec", "convex", "sub", "link", "canvas", "sound",
+        "pcm",
+    ];
+
+    /// The descendants of `Test.img/canvas`, depth first.
+    const CANVAS: [&str; 11] = [
+        "canvas/origin",
+        "canvas/head",
+        "canvas/lt",
+        "canvas/rb",
+        "canvas/map",
+        "canvas/map/navel",
+        "canvas/map/hand",
+        "canvas/delay",
+        "canvas/z",
+        "canvas/a0",
+        "canvas/a1",
+    ];
+
+    fn paths<N: MapleNode<Item = N>>(walk: impl Iterator<Item = Box<N>>) -> Vec<String> {
+        walk.map(|node| node.path().to_owned()).collect()
+    }
+
+    fn test_img(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| format!("/Test.img/{}", name)).collect()
+    }
+
+    fn mob_img(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| format!("/Mob/Mob.img/{}", name)).collect()
+    }
+
+    #[test]
+    fn depth_first() {
+        let file = fixture::open();
+        let root = file.open_root().unwrap();
+        let mut expected: Vec<String> = vec!["/Mob".into(), "/Mob/Mob.img".into()];
+        expected.extend(mob_img(&["hp", "links", "links/up", "links/dangling", "links/a", "links/b"]));
+        expected.extend(mob_img(&["icon", "icon/z", "icon/a0", "stand"]));
+        expected.extend(mob_img(&["stand/10", "stand/10/delay", "stand/1", "stand/1/delay", "stand/zigzag"]));
+        expected.extend(mob_img(&["stand/2", "stand/0", "stand/0/delay", "stand/repeat"]));
+        expected.push("/Test.img".into());
+        for name in TEST {
+            expected.extend(test_img(&[name]));
+            match name {
+                "sub" => expected.extend(test_img(&["sub/x"])),
+                "canvas" => expected.extend(test_img(&CANVAS)),
+                _ => {}
+            }
+        }
+        assert_eq!(paths(root.walk_dfs()), expected);
+    }
+
+    #[test]
+    fn breadth_first() {
+        let file = fixture::open();
+        let root = file.open_root().unwrap();
+        let mut expected: Vec<String> = vec!["/Mob".into(), "/Test.img".into(), "/Mob/Mob.img".into()];
+        expected.extend(test_img(&TEST));
+        expected.extend(mob_img(&["hp", "links", "icon", "stand"]));
+        expected.extend(test_img(&["sub/x"]));
+        expected.extend(test_img(&CANVAS).into_iter().filter(|path| !path.contains("/map/")));
+        expected.extend(mob_img(&["links/up", "links/dangling", "links/a", "links/b", "icon/z", "icon/a0"]));
+        expected.extend(mob_img(&["stand/10", "stand/1", "stand/zigzag", "stand/2", "stand/0", "stand/repeat"]));
+        expected.extend(test_img(&["canvas/map/navel", "canvas/map/hand"]));
+        expected.extend(mob_img(&["stand/10/delay", "stand/1/delay", "stand/0/delay"]));
+        assert_eq!(paths(root.walk_bfs()), expected);
+    }
+
+    #[test]
+    fn max_depth() {
+        let file = fixture::open();
+        let root = file.open_root().unwrap();
+        let mut expected: Vec<String> = vec!["/Mob".into(), "/Mob/Mob.img".into(), "/Test.img".into()];
+        expected.extend(test_img(&TEST));
+        assert_eq!(paths(root.walk_dfs().max_depth(2)), expected);
+        assert_eq!(paths(root.walk_bfs().max_depth(1)), ["/Mob", "/Test.img"]);
+        assert_eq!(paths(root.walk_dfs().max_depth(0)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn prune() {
+        let file = fixture::open();
+        let test = file.open_root().child("Test.img").unwrap();
+        let mut visited = vec![];
+        let walked = paths(test.walk_dfs().filter_tree(|node| {
+            visited.push(node.path().to_owned());
+            node.name() != Ok("sub")
+        }));
+        assert!(!walked.iter().any(|path| path.starts_with("/Test.img/sub")));
+        assert!(walked.contains(&"/Test.img/canvas/origin".to_owned()));
+        // the subtree of sub is never visited
+        assert!(!visited.contains(&"/Test.img/sub/x".to_owned()));
+        assert_eq!(walked.len() + 1, visited.len());
+    }
+
+    #[test]
+    fn skip_canvas() {
+        let file = fixture::open();
+        let test = file.open_root().child("Test.img").unwrap();
+        let mut expected = test_img(&TEST);
+        expected.insert(10, "/Test.img/sub/x".to_owned());
+        assert_eq!(paths(test.walk_dfs().descend_canvas(false)), expected);
+    }
+}
@@ -1,112 +1,325 @@
 use crate::{
     c_wz::*,
-    node::{Dtype, ImageBuffer, MapleNode},
+    error::{Result, WzError},
+    node::{fmt_node, follow_links, AudioBuffer, Dtype, ImageBuffer, MapleNode},
+    path::WzPath,
+    sound::split_wav,
 };
-use anyhow::{bail, Result};
 use num_traits::FromPrimitive;
 use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
     ffi::{CStr, CString},
     fmt::{Debug, Formatter},
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom},
     marker::PhantomData,
+    path::PathBuf,
     ptr::NonNull,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
 };
 
-pub struct WzNode {
+/// A node of an opened [`WzFile`], which can not outlive the file.
+/// Using a node after its file is dropped does not compile:
+///
+/// ```compile_fail,E0597
+/// use wz::{node::MapleNode, wz::WzCtx};
+///
+/// let ctx = WzCtx::new()?;
+/// let root = {
+///     let file = ctx.open_file("Mob.wz")?;
+///     file.open_root()?
+/// };
+/// println!("{}", root.len());
+/// # Ok::<(), wz::error::WzError>(())
+/// ```
+///
+/// Neither does using a str of a node after the node is dropped:
+///
+/// ```compile_fail,E0597
+/// use wz::{node::MapleNode, wz::WzCtx};
+///
+/// let ctx = WzCtx::new()?;
+/// let file = ctx.open_file("String.wz")?;
+/// let name = {
+///     let node = file.open_root()?.child("Mob.img/100100/name")?;
+///     node.str()?
+/// };
+/// println!("{}", name);
+/// # Ok::<(), wz::error::WzError>(())
+/// ```
+pub struct WzNode<'f> {
     pointer: NonNull<wznode>,
-    pub path: Option<String>,
-    marker: PhantomData<*const wznode>,
+    root: NonNull<wznode>,
+    pub path: WzPath,
+    follow_uol: bool,
+    /// The `.img` containing node, which libwz keeps parsed while node is alive.
+    image: Option<String>,
+    images: &'f RefCell<Images>,
 }
 
-impl WzNode {
-    pub fn new(pointer: NonNull<wznode>, path: &str) -> Self {
-        WzNode { pointer, path: Some(path.to_owned()), marker: Default::default() }
+impl<'f> WzNode<'f> {
+    fn new(pointer: NonNull<wznode>, root: NonNull<wznode>, path: WzPath, images: &'f RefCell<Images>) -> Self {
+        let image = image_of(&path);
+        if let Some(image) = &image {
+            images.borrow_mut().acquire(image);
+        }
+        WzNode { pointer, root, path, follow_uol: false, image, images }
+    }
+
+    /// Make [`MapleNode::child`] transparently follow [`Dtype::UOL`] nodes met on the way.
+    /// The mode is inherited by every node opened from this one.
+    pub fn set_follow_uol(&mut self, follow: bool) {
+        self.follow_uol = follow;
+    }
+
+    /// Whether [`MapleNode::child`] follows [`Dtype::UOL`] nodes.
+    pub fn follows_uol(&self) -> bool {
+        self.follow_uol
+    }
+
+    /// Free the parsed `.img` containing node with `wz_close_node` once every node of it is dropped;
+    /// visiting it again parses it anew. Directories are not affected.
+    pub fn unload(&self) {
+        if let Some(image) = &self.image {
+            self.images.borrow_mut().unload(image);
+        }
+    }
+
+    fn path_str(&self) -> &str {
+        self.path.as_str()
+    }
+
+    fn with_pointer(&self, pointer: NonNull<wznode>, path: WzPath) -> Box<WzNode<'f>> {
+        let mut node = WzNode::new(pointer, self.root, path, self.images);
+        node.follow_uol = self.follow_uol;
+        Box::new(node)
+    }
+
+    fn mismatch(&self, expected: Dtype) -> WzError {
+        WzError::mismatch(self, expected)
+    }
+
+    /// Report a libwz call which failed after the type of node was accepted.
+    fn ffi(&self, call: &'static str) -> WzError {
+        WzError::Ffi { path: self.path_str().to_owned(), call }
+    }
+
+    /// Check the type of node before calling libwz, reporting the first of `accepted` on mismatch.
+    fn expect(&self, accepted: &[Dtype]) -> Result<()> {
+        if accepted.contains(&self.dtype()?) {
+            Ok(())
+        } else {
+            Err(self.mismatch(accepted[0]))
+        }
+    }
+
+    /// Decode a C string owned by the node. Invalid utf8 means the string was decrypted with a wrong key.
+    fn c_str(&self, s: *const std::os::raw::c_char) -> Result<&str> {
+        unsafe { CStr::from_ptr(s) }.to_str().map_err(|_| WzError::Decryption { path: self.path_str().to_owned() })
+    }
+
+    /// Open node with given absolute path (e.g. "/Mob/100100.img/info") from the root of its file.
+    fn open_absolute(&self, path: &WzPath) -> Result<Box<WzNode<'f>>> {
+        if path.is_root() {
+            return Ok(self.with_pointer(self.root, WzPath::root()));
+        }
+        let relative = path.as_str().trim_start_matches('/');
+        let c_path = CString::new(relative).map_err(|_| WzError::InvalidPath { path: path.to_string() })?;
+        let node = unsafe { wz_open_node(self.root.as_ptr(), c_path.as_ptr()) };
+        NonNull::new(node)
+            .map(|node| self.with_pointer(node, path.clone()))
+            .ok_or_else(|| WzError::NotFound { path: path.to_string() })
+    }
+
+    /// Open descendant at a path of names (e.g. "info/icon") without following UOLs.
+    fn open_child(&self, path: &str) -> Result<Box<WzNode<'f>>> {
+        let child_path = self.path.join(path).ok_or_else(|| WzError::InvalidPath { path: path.to_owned() })?;
+        let c_path = CString::new(path).map_err(|_| WzError::InvalidPath { path: child_path.to_string() })?;
+        let node = unsafe { wz_open_node(self.pointer.as_ptr(), c_path.as_ptr()) };
+        match NonNull::new(node) {
+            Some(node) => Ok(self.with_pointer(node, child_path)),
+            None => Err(WzError::NotFound { path: child_path.to_string() }),
+        }
+    }
+
+    /// Follow the chain of UOLs starting at node until a non-UOL node.
+    fn follow(node: Box<WzNode<'f>>) -> Result<Box<WzNode<'f>>> {
+        follow_links(node, WzNode::open_absolute)
     }
 }
 
-impl Debug for WzNode {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut path = "".to_string();
-        if let Some(s) = self.path.as_ref() {
-            path = s.clone();
-        }
-        let mut dtype = "Error".to_string();
-        if let Ok(Some(t)) = self.dtype() {
-            dtype = t.to_str().to_owned();
-        }
-
-        let mut val = String::new();
-        if let Ok(Some(t)) = self.dtype() {
-            match t {
-                Dtype::NIL => {}
-                Dtype::I16 | Dtype::I32 => {
-                    if let Ok(Some(v)) = self.int32() {
-                        val = v.to_string();
-                    }
-                }
-                Dtype::I64 => {
-                    if let Ok(Some(v)) = self.int64() {
-                        val = v.to_string();
-                    }
-                }
-                Dtype::F32 => {
-                    if let Ok(Some(v)) = self.float32() {
-                        val = v.to_string();
-                    }
-                }
-                Dtype::F64 => {
-                    if let Ok(Some(v)) = self.float64() {
-                        val = v.to_string();
-                    }
-                }
-                Dtype::VEC => {
-                    if let Ok(Some(v)) = self.vec() {
-                        val = format!("vec({})", v);
-                    }
-                }
-                Dtype::UNK => {}
-                Dtype::ARY => {
-                    val = format!("child num={}", self.len());
-                }
-                Dtype::IMG => {
-                    if let Ok(Some(p)) = self.img() {
-                        val = format!("dim:{}x{},child num={}", p.width, p.height, self.len());
-                    }
-                }
-                Dtype::VEX => {}
-                Dtype::AO => {}
-                Dtype::UOL => {}
-                Dtype::STR => {
-                    if let Ok(Some(s)) = self.str() {
-                        val = s.to_string();
-                    }
+impl Drop for WzNode<'_> {
+    fn drop(&mut self) {
+        if let Some(image) = &self.image {
+            self.images.borrow_mut().release(image);
+        }
+    }
+}
+
+/// Get the path of the `.img` containing the node at path, [`None`] for directories.
+fn image_of(path: &WzPath) -> Option<String> {
+    let mut image = WzPath::root();
+    for segment in path.segments() {
+        image = image.child(segment)?;
+        if segment.ends_with(".img") {
+            return Some(image.as_str().to_owned());
+        }
+    }
+    None
+}
+
+/// The `.img`s libwz parsed for the nodes of a file, each freed with `wz_close_node` once no node of it is alive
+/// and it was unloaded or is beyond the budget.
+struct Images {
+    file: NonNull<wzfile>,
+    loaded: HashMap<String, Loaded>,
+    /// Images no node of which is alive, least recently released first, with the release they were queued at.
+    /// Entries of images visited or closed since are stale and skipped.
+    idle: VecDeque<(String, u64)>,
+    budget: Option<usize>,
+    clock: u64,
+}
+
+struct Loaded {
+    nodes: usize,
+    released: u64,
+    unload: bool,
+}
+
+impl Images {
+    fn new(file: NonNull<wzfile>) -> Self {
+        Images { file, loaded: HashMap::new(), idle: VecDeque::new(), budget: None, clock: 0 }
+    }
+
+    fn acquire(&mut self, image: &str) {
+        let loaded = self.loaded.entry(image.to_owned()).or_insert(Loaded { nodes: 0, released: 0, unload: false });
+        loaded.nodes += 1;
+    }
+
+    fn release(&mut self, image: &str) {
+        self.clock += 1;
+        let unload = match self.loaded.get_mut(image) {
+            Some(loaded) => {
+                loaded.nodes -= 1;
+                if loaded.nodes > 0 {
+                    return;
                 }
+                loaded.released = self.clock;
+                loaded.unload
+            }
+            None => return,
+        };
+        if unload {
+            self.close(image);
+        } else if self.budget.is_some() {
+            self.idle.push_back((image.to_owned(), self.clock));
+            self.trim();
+        }
+    }
+
+    fn is_idle(&self, image: &str, released: u64) -> bool {
+        self.loaded.get(image).is_some_and(|loaded| loaded.nodes == 0 && loaded.released == released)
+    }
+
+    fn unload(&mut self, image: &str) {
+        if let Some(loaded) = self.loaded.get_mut(image) {
+            loaded.unload = true;
+        }
+    }
+
+    fn unload_all(&mut self) {
+        let idle: Vec<String> =
+            self.loaded.iter().filter(|(_, loaded)| loaded.nodes == 0).map(|(image, _)| image.clone()).collect();
+        for image in idle {
+            self.close(&image);
+        }
+        for loaded in self.loaded.values_mut() {
+            loaded.unload = true;
+        }
+        self.idle.clear();
+    }
+
+    fn set_budget(&mut self, budget: Option<usize>) {
+        self.budget = budget;
+        let mut idle: Vec<(String, u64)> = self
+            .loaded
+            .iter()
+            .filter(|(_, loaded)| loaded.nodes == 0)
+            .map(|(image, loaded)| (image.clone(), loaded.released))
+            .collect();
+        idle.sort_unstable_by_key(|&(_, released)| released);
+        self.idle = idle.into();
+        self.trim();
+    }
+
+    /// Close the least recently released images until the count fits the budget.
+    /// Images with a live node are kept even beyond it.
+    fn trim(&mut self) {
+        let budget = match self.budget {
+            Some(budget) => budget,
+            None => return,
+        };
+        while self.loaded.len() > budget {
+            match self.idle.pop_front() {
+                Some((image, released)) if self.is_idle(&image, released) => self.close(&image),
+                Some(_) => {}
+                None => break,
+            }
+        }
+        // stale entries pile up while the images fit the budget
+        if self.idle.len() > 2 * self.loaded.len() + 16 {
+            let idle = std::mem::take(&mut self.idle);
+            self.idle = idle.into_iter().filter(|(image, released)| self.is_idle(image, *released)).collect();
+        }
+    }
+
+    /// Free the parsed content of image, which no node points into.
+    fn close(&mut self, image: &str) {
+        self.loaded.remove(image);
+        let c_path = match CString::new(image.trim_start_matches('/')) {
+            Ok(c_path) => c_path,
+            Err(_) => return,
+        };
+        unsafe {
+            let root = wz_open_root(self.file.as_ptr());
+            let node = if root.is_null() { root } else { wz_open_node(root, c_path.as_ptr()) };
+            if !node.is_null() {
+                wz_close_node(node);
             }
         }
+    }
+}
 
-        let _ = write!(f, "WzNode Path[{path}] Type[{dtype}] Value[{val}]", path = path, dtype = dtype, val = val);
-        Ok(())
+impl Debug for WzNode<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fmt_node(self, f)
     }
 }
 
-impl WzNode {
-    pub fn iter(&self) -> WzNodeIter<'_> {
+impl<'f> WzNode<'f> {
+    pub fn iter(&self) -> WzNodeIter<'_, 'f> {
         WzNodeIter::new(self)
     }
 }
 
-pub struct WzNodeIter<'a> {
-    base: &'a WzNode,
+pub struct WzNodeIter<'a, 'f> {
+    base: &'a WzNode<'f>,
     index: u32,
 }
 
-impl<'a> WzNodeIter<'a> {
-    pub fn new(base: &'a WzNode) -> Self {
+impl<'a, 'f> WzNodeIter<'a, 'f> {
+    pub fn new(base: &'a WzNode<'f>) -> Self {
         Self { base, index: 0 }
     }
 }
 
-impl<'a> Iterator for WzNodeIter<'a> {
-    type Item = Box<WzNode>;
+impl<'a, 'f> Iterator for WzNodeIter<'a, 'f> {
+    type Item = Box<WzNode<'f>>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.index >= self.base.len() {
@@ -114,7 +327,7 @@ impl<'a> Iterator for WzNodeIter<'a> {
         }
         let child_node = self.base.child_at(self.index);
         self.index += 1;
-        child_node
+        child_node.ok()
     }
 }
 
@@ -124,20 +337,68 @@ pub struct WzCtx {
 }
 
 impl WzCtx {
+    /// Create a context with the keys built into libwz, which are tried in turn on every file.
+    ///
+    /// `wz_init_ctx` takes no options, so choosing the region, raw IV, AES user key or guessing strategy,
+    /// and reading which key a file was opened with, need the `pure` backend:
+    /// `pure::WzCtx::builder()` and `pure::WzFile::region`.
     pub fn new() -> Result<Self> {
         let pointer = unsafe { wz_init_ctx() };
         let pointer = NonNull::new(pointer);
         match pointer {
-            Some(pointer) => Ok(WzCtx { pointer, marker: PhantomData::default() }),
-            None => bail!("new WzCtx failed!"),
+            Some(pointer) => Ok(WzCtx { pointer, marker: PhantomData }),
+            None => Err(WzError::ContextInit),
         }
     }
 
-    /// open wz file with given path.
-    pub fn open_file(&self, path: &str) -> Result<Option<WzFile>> {
-        let path = CString::new(path)?;
-        let file = unsafe { wz_open_file(path.as_ptr(), self.pointer.as_ptr()) };
-        Ok(NonNull::new(file).map(|f| WzFile::new(f)))
+    /// open wz file with given path. The file can not outlive the context.
+    pub fn open_file(&self, path: &str) -> Result<WzFile<'_>> {
+        let c_path = CString::new(path).map_err(|_| WzError::InvalidPath { path: path.to_owned() })?;
+        let file = unsafe { wz_open_file(c_path.as_ptr(), self.pointer.as_ptr()) };
+        NonNull::new(file).map(WzFile::new).ok_or_else(|| WzError::FileNotOpen { path: path.to_owned() })
+    }
+
+    /// open wz file held in memory, e.g. downloaded or embedded with `include_bytes!`.
+    /// libwz only reads from a path, so the bytes are copied to a temporary file removed along with the file;
+    /// the `pure` backend reads them in place. Errors of the file carry an empty path.
+    pub fn open_bytes(&self, data: Arc<[u8]>) -> Result<WzFile<'_>> {
+        self.open_reader(std::io::Cursor::new(data))
+    }
+
+    /// open wz file read from the start of a reader, which is copied to a temporary file
+    /// like [`WzCtx::open_bytes`]. Errors of the file carry an empty path.
+    ///
+    /// Memory mapping a file, `pure::WzCtx::open_mmap`, needs the `pure` backend.
+    pub fn open_reader<R: Read + Seek>(&self, mut reader: R) -> Result<WzFile<'_>> {
+        let not_open = |_| WzError::FileNotOpen { path: String::new() };
+        let (temp, mut written) = TempFile::create().map_err(not_open)?;
+        reader.seek(SeekFrom::Start(0)).map_err(not_open)?;
+        std::io::copy(&mut reader, &mut written).map_err(not_open)?;
+        drop(written);
+        let path = temp.0.to_str().ok_or_else(|| WzError::FileNotOpen { path: String::new() })?;
+        let mut file = self.open_file(path).map_err(|_| WzError::FileNotOpen { path: String::new() })?;
+        file.temp = Some(temp);
+        Ok(file)
+    }
+}
+
+/// A file in the temporary directory, removed when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    /// Create a new empty file, opened for writing.
+    fn create() -> std::io::Result<(TempFile, File)> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("wz-{}-{}.wz", std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        Ok((TempFile(path), file))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
     }
 }
 
@@ -149,24 +410,52 @@ impl Drop for WzCtx {
     }
 }
 
-pub struct WzFile {
+/// A wz file opened by a [`WzCtx`], which can not outlive the context.
+/// Memory mapping, and borrowing the stored (compressed) payloads of canvases and sounds,
+/// need the `pure` backend, see `pure::WzNode::raw_img`.
+///
+/// Every `.img` visited stays parsed until it is unloaded, see [`WzNode::unload`] and [`WzFile::set_image_budget`].
+/// libwz can not tell the memory an image takes, so the budget counts images rather than bytes
+/// like `pure::WzFile::set_memory_budget`.
+pub struct WzFile<'c> {
     pointer: NonNull<wzfile>,
-    marker: PhantomData<*const wzfile>,
+    images: RefCell<Images>,
+    // removed after the file is closed
+    temp: Option<TempFile>,
+    marker: PhantomData<&'c wzctx>,
 }
 
-impl WzFile {
-    pub fn new(pointer: NonNull<wzfile>) -> Self {
-        WzFile { pointer, marker: Default::default() }
+impl<'c> WzFile<'c> {
+    pub(crate) fn new(pointer: NonNull<wzfile>) -> Self {
+        WzFile { pointer, images: RefCell::new(Images::new(pointer)), temp: None, marker: Default::default() }
     }
 
-    /// open root node with given wzfile.
-    pub fn open_root<'a>(&self) -> Result<Option<Box<WzNode>>> {
+    /// open root node with given wzfile. The node can not outlive the file.
+    pub fn open_root(&self) -> Result<Box<WzNode<'_>>> {
         let root = unsafe { wz_open_root(self.pointer.as_ptr()) };
-        Ok(NonNull::new(root).map(|root| Box::new(WzNode::new(root, ""))))
+        NonNull::new(root)
+            .map(|root| Box::new(WzNode::new(root, root, WzPath::root(), &self.images)))
+            .ok_or_else(|| WzError::NotFound { path: String::new() })
+    }
+
+    /// Limit the number of parsed `.img`s, freeing the least recently used ones beyond it
+    /// once no node of them is alive. [`None`] (the default) keeps every image.
+    pub fn set_image_budget(&self, budget: Option<usize>) {
+        self.images.borrow_mut().set_budget(budget);
+    }
+
+    /// Get the number of parsed `.img`s kept by the file.
+    pub fn loaded_images(&self) -> usize {
+        self.images.borrow().loaded.len()
+    }
+
+    /// Unload every parsed `.img`, see [`WzNode::unload`].
+    pub fn unload_all(&self) {
+        self.images.borrow_mut().unload_all();
     }
 }
 
-impl Drop for WzFile {
+impl Drop for WzFile<'_> {
     fn drop(&mut self) {
         unsafe {
             wz_close_file(self.pointer.as_ptr());
@@ -174,96 +463,123 @@ impl Drop for WzFile {
     }
 }
 
-pub struct UnsafeSend<T>(pub T);
-
-unsafe impl<T> Send for UnsafeSend<T> {}
-
-impl<T: MapleNode> MapleNode for &mut T {
-    type Item = T::Item;
-
-    fn child<S: AsRef<str>>(&self, path: S) -> Option<Box<Self::Item>> {
-        (**self).child(path)
-    }
-
-    fn child_at(&self, i: u32) -> Option<Box<Self::Item>> {
-        (**self).child_at(i)
-    }
+/// A wz file with its own context, which can be shared between threads.
+///
+/// libwz is not thread safe, so every access locks the file for the duration of [`SharedWzFile::with_root`],
+/// and nodes can not escape the closure. Any [`MapleNode`] operation is allowed inside it;
+/// concurrent callers simply wait for each other. The lock is not reentrant: calling
+/// [`with_root`](SharedWzFile::with_root), [`set_image_budget`](SharedWzFile::set_image_budget) or
+/// [`unload_all`](SharedWzFile::unload_all) on the same file from inside the closure deadlocks.
+pub struct SharedWzFile {
+    inner: Mutex<SharedInner>,
+    path: String,
+}
 
-    fn len(&self) -> u32 {
-        (**self).len()
-    }
+struct SharedInner {
+    file: NonNull<wzfile>,
+    images: RefCell<Images>,
+    // dropped after the file is closed
+    _ctx: WzCtx,
+}
 
-    fn dtype(&self) -> Result<Option<Dtype>> {
-        (**self).dtype()
-    }
+// Safety: libwz state is only touched by the thread holding the lock.
+unsafe impl Send for SharedInner {}
 
-    fn int32(&self) -> Result<Option<i32>> {
-        (**self).int32()
+impl SharedWzFile {
+    /// open wz file with given path in a new context.
+    pub fn open(path: &str) -> Result<Self> {
+        let ctx = WzCtx::new()?;
+        let c_path = CString::new(path).map_err(|_| WzError::InvalidPath { path: path.to_owned() })?;
+        let file = unsafe { wz_open_file(c_path.as_ptr(), ctx.pointer.as_ptr()) };
+        let file = NonNull::new(file).ok_or_else(|| WzError::FileNotOpen { path: path.to_owned() })?;
+        let inner = SharedInner { file, images: RefCell::new(Images::new(file)), _ctx: ctx };
+        Ok(SharedWzFile { inner: Mutex::new(inner), path: path.to_owned() })
     }
 
-    fn int64(&self) -> Result<Option<i64>> {
-        (**self).int64()
+    /// Get the path the file was opened with.
+    pub fn path(&self) -> &str {
+        &self.path
     }
 
-    fn float32(&self) -> Result<Option<f32>> {
-        (**self).float32()
+    /// Lock the file and run `f` on its root node. The lock is held until `f` returns,
+    /// so `f` must not call any method of the same file, which would deadlock.
+    pub fn with_root<R, F: FnOnce(&WzNode<'_>) -> R>(&self, f: F) -> Result<R> {
+        let inner = self.lock();
+        let root = unsafe { wz_open_root(inner.file.as_ptr()) };
+        let root = NonNull::new(root).ok_or_else(|| WzError::NotFound { path: String::new() })?;
+        let root = WzNode::new(root, root, WzPath::root(), &inner.images);
+        Ok(f(&root))
     }
 
-    fn float64(&self) -> Result<Option<f64>> {
-        (**self).float64()
+    /// Limit the number of parsed `.img`s, see [`WzFile::set_image_budget`].
+    pub fn set_image_budget(&self, budget: Option<usize>) {
+        self.lock().images.borrow_mut().set_budget(budget);
     }
 
-    fn str(&self) -> Result<Option<&'static str>> {
-        (**self).str()
+    /// Unload every parsed `.img`, see [`WzNode::unload`].
+    pub fn unload_all(&self) {
+        self.lock().images.borrow_mut().unload_all();
     }
 
-    fn name(&self) -> Result<Option<&'static str>> {
-        (**self).name()
+    fn lock(&self) -> std::sync::MutexGuard<'_, SharedInner> {
+        // a panic inside `with_root` leaves libwz consistent, so a poisoned lock is still usable
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
     }
+}
 
-    fn vex_len(&self) -> Result<u32> {
-        (**self).vex_len()
+impl Drop for SharedInner {
+    fn drop(&mut self) {
+        unsafe {
+            wz_close_file(self.file.as_ptr());
+        }
     }
+}
 
-    fn vec(&self) -> Result<Option<glam::Vec2>> {
-        (**self).vec()
-    }
+/// Asserts `Send` without any synchronization, which is unsound for libwz handles.
+#[deprecated(note = "use SharedWzFile, which locks libwz")]
+pub struct UnsafeSend<T>(pub T);
 
-    fn img<'a>(&self) -> Result<Option<ImageBuffer<'a>>> {
-        (**self).img()
-    }
-}
+#[allow(deprecated)]
+unsafe impl<T> Send for UnsafeSend<T> {}
 
-impl MapleNode for WzNode {
-    type Item = WzNode;
+impl<'f> MapleNode for WzNode<'f> {
+    type Item = WzNode<'f>;
 
-    fn child<S: AsRef<str>>(&self, path: S) -> Option<Box<Self::Item>> {
-        let self_path = match &self.path {
-            Some(path) => &path,
-            None => "",
-        };
-        let child_path = &*format!("{}/{}", self_path, path.as_ref());
-        let path = CString::new(path.as_ref()).unwrap();
-        let node = unsafe { wz_open_node(self.pointer.as_ptr(), path.as_ptr()) };
-        NonNull::new(node).map(|node| Box::new(WzNode::new(node, child_path)))
+    fn child<S: AsRef<str>>(&self, path: S) -> Result<Box<Self::Item>> {
+        let segments: Vec<&str> = path.as_ref().split('/').filter(|s| !s.is_empty()).collect();
+        if segments.is_empty() {
+            return Err(WzError::InvalidPath { path: path.as_ref().to_owned() });
+        }
+        // libwz walks a plain path of names itself
+        if !self.follow_uol && !segments.iter().any(|s| matches!(*s, "." | "..")) {
+            return self.open_child(&segments.join("/"));
+        }
+        let mut node = self.with_pointer(self.pointer, self.path.clone());
+        for segment in segments {
+            node = match segment {
+                "." => node,
+                ".." => node.parent()?,
+                name if node.follow_uol => WzNode::follow(node.open_child(name)?)?,
+                name => node.open_child(name)?,
+            };
+        }
+        Ok(node)
     }
 
-    fn child_at(&self, i: u32) -> Option<Box<Self::Item>> {
-        let self_path = match &self.path {
-            Some(path) => &path,
-            None => "",
-        };
+    fn child_at(&self, i: u32) -> Result<Box<Self::Item>> {
         let node = unsafe { wz_open_node_at(self.pointer.as_ptr(), i) };
-        NonNull::new(node).map(|node| {
-            let mut node = WzNode::new(node, "");
-            let node_name = match node.name() {
-                Ok(Some(node_name)) => node_name,
-                _ => "",
-            };
-            let child_path = format!("{}/{}", self_path, node_name);
-            node.path = Some(child_path);
-            Box::new(node)
-        })
+        match NonNull::new(node) {
+            Some(node) => {
+                let name = unsafe { wz_get_name(node.as_ptr()) };
+                if name.is_null() {
+                    return Err(self.ffi("wz_get_name"));
+                }
+                let name = self.c_str(name)?;
+                let path = self.path.child(name).ok_or_else(|| WzError::InvalidPath { path: name.to_owned() })?;
+                Ok(self.with_pointer(node, path))
+            }
+            None => Err(WzError::IndexOutOfRange { path: self.path_str().to_owned(), index: i, len: self.len() }),
+        }
     }
 
     fn len(&self) -> u32 {
@@ -276,97 +592,148 @@ impl MapleNode for WzNode {
         }
     }
 
-    fn dtype(&self) -> Result<Option<Dtype>> {
+    fn path(&self) -> &str {
+        self.path_str()
+    }
+
+    fn parent(&self) -> Result<Box<Self::Item>> {
+        let parent = self.path.parent().ok_or_else(|| WzError::NotFound { path: "/..".to_owned() })?;
+        self.open_absolute(&parent)
+    }
+
+    fn root(&self) -> Result<Box<Self::Item>> {
+        self.open_absolute(&WzPath::root())
+    }
+
+    fn dtype(&self) -> Result<Dtype> {
         let wz_type = unsafe { wz_get_type(self.pointer.as_ptr()) };
 
-        Ok(FromPrimitive::from_u8(wz_type))
+        FromPrimitive::from_u8(wz_type)
+            .ok_or_else(|| WzError::UnknownType { path: self.path_str().to_owned(), tag: wz_type })
     }
 
-    fn int32(&self) -> Result<Option<i32>> {
+    fn int32(&self) -> Result<i32> {
+        self.expect(&[Dtype::I32, Dtype::I16])?;
         let mut val: wz_int32_t = 0;
         let ret = unsafe { wz_get_int(&mut val, self.pointer.as_ptr()) };
         if ret == 1 {
-            bail!("get int32 error");
+            return Err(self.ffi("wz_get_int"));
         }
-        Ok(Some(val as i32))
+        Ok(val as i32)
     }
 
-    fn int64(&self) -> Result<Option<i64>> {
+    fn int64(&self) -> Result<i64> {
+        self.expect(&[Dtype::I64])?;
         let mut val: wz_int64_t = 0;
         let ret = unsafe { wz_get_i64(&mut val, self.pointer.as_ptr()) };
         if ret == 1 {
-            bail!("get int64 error");
+            return Err(self.ffi("wz_get_i64"));
         }
-        Ok(Some(val as i64))
+        Ok(val as i64)
     }
 
-    fn float32(&self) -> Result<Option<f32>> {
+    fn float32(&self) -> Result<f32> {
+        self.expect(&[Dtype::F32])?;
         let mut val = 0.0f32;
         let ret = unsafe { wz_get_f32(&mut val, self.pointer.as_ptr()) };
         if ret == 1 {
-            bail!("get float32 error");
+            return Err(self.ffi("wz_get_f32"));
         }
-        Ok(Some(val))
+        Ok(val)
     }
 
-    fn float64(&self) -> Result<Option<f64>> {
+    fn float64(&self) -> Result<f64> {
+        self.expect(&[Dtype::F64])?;
         let mut val = 0.0f64;
         let ret = unsafe { wz_get_f64(&mut val, self.pointer.as_ptr()) };
         if ret == 1 {
-            bail!("get float64 error");
+            return Err(self.ffi("wz_get_f64"));
         }
-        Ok(Some(val))
+        Ok(val)
     }
 
-    fn str(&self) -> Result<Option<&'static str>> {
+    fn str(&self) -> Result<&str> {
+        self.expect(&[Dtype::STR])?;
         let s = unsafe { wz_get_str(self.pointer.as_ptr()) };
 
         if s.is_null() {
-            bail!("get string error");
+            return Err(self.ffi("wz_get_str"));
         }
+        self.c_str(s)
+    }
 
-        unsafe {
-            let s = CStr::from_ptr(s).to_str()?;
-            Ok(Some(s))
+    fn uol(&self) -> Result<&str> {
+        self.expect(&[Dtype::UOL])?;
+        let s = unsafe { wz_get_str(self.pointer.as_ptr()) };
+
+        if s.is_null() {
+            return Err(self.ffi("wz_get_str"));
         }
+        self.c_str(s)
+    }
+
+    fn resolve(&self) -> Result<Box<Self::Item>> {
+        self.expect(&[Dtype::UOL])?;
+        WzNode::follow(self.with_pointer(self.pointer, self.path.clone()))
     }
 
-    fn name(&self) -> Result<Option<&'static str>> {
+    fn name(&self) -> Result<&str> {
         let s = unsafe { wz_get_name(self.pointer.as_ptr()) };
 
         if s.is_null() {
-            bail!("get string error");
-        }
-        unsafe {
-            let s = CStr::from_ptr(s).to_str()?;
-            Ok(Some(s))
+            return Err(self.ffi("wz_get_name"));
         }
+        self.c_str(s)
     }
 
     fn vex_len(&self) -> Result<u32> {
+        self.expect(&[Dtype::VEX])?;
         unsafe {
             let mut len: wz_uint32_t = 0;
             let ret = wz_get_vex_len(&mut len, self.pointer.as_ptr());
             if ret == 1 {
-                bail!("vec() failed");
+                return Err(self.ffi("wz_get_vex_len"));
             }
             Ok(len)
         }
     }
 
-    fn vec(&self) -> Result<Option<glam::Vec2>> {
+    fn vex_at(&self, i: u32) -> Result<glam::IVec2> {
+        let len = self.vex_len()?;
+        if i >= len {
+            return Err(WzError::IndexOutOfRange { path: self.path_str().to_owned(), index: i, len });
+        }
+        unsafe {
+            let mut x: wz_int32_t = 0;
+            let mut y: wz_int32_t = 0;
+            let ret = wz_get_vex_at(&mut x, &mut y, i, self.pointer.as_ptr());
+            if ret == 1 {
+                return Err(self.ffi("wz_get_vex_at"));
+            }
+            Ok(glam::IVec2::new(x, y))
+        }
+    }
+
+    fn vex(&self) -> Result<Vec<glam::IVec2>> {
+        let len = self.vex_len()?;
+        (0..len).map(|i| self.vex_at(i)).collect()
+    }
+
+    fn vec(&self) -> Result<glam::Vec2> {
+        self.expect(&[Dtype::VEC])?;
         unsafe {
             let mut x: wz_int32_t = 0;
             let mut y: wz_int32_t = 0;
             let ret = wz_get_vec(&mut x, &mut y, self.pointer.as_ptr());
             if ret == 1 {
-                bail!("vec() failed");
+                return Err(self.ffi("wz_get_vec"));
             }
-            Ok(Some(glam::Vec2::new(x as f32, y as f32)))
+            Ok(glam::Vec2::new(x as f32, y as f32))
         }
     }
 
-    fn img<'a>(&self) -> Result<Option<ImageBuffer<'a>>> {
+    fn img(&self) -> Result<ImageBuffer<'_>> {
+        self.expect(&[Dtype::IMG])?;
         unsafe {
             let mut w: wz_uint32_t = 0;
             let mut h: wz_uint32_t = 0;
@@ -374,97 +741,81 @@ impl MapleNode for WzNode {
             let mut s: wz_uint8_t = 0;
             let ret = wz_get_img(&mut w, &mut h, &mut d, &mut s, self.pointer.as_ptr());
             if ret.is_null() {
-                bail!("img() failed");
+                return Err(self.ffi("wz_get_img"));
             }
-            let len = (w * h * 4) as usize;
-            let data: &'a mut [u8] = std::slice::from_raw_parts_mut(ret, len);
-            Ok(Some(ImageBuffer { width: w, height: h, depth: d, scale: s, data }))
-        }
-    }
-}
-
-impl<T: MapleNode> MapleNode for Option<Box<T>> {
-    type Item = T::Item;
-
-    fn child<S: AsRef<str>>(&self, path: S) -> Option<Box<Self::Item>> {
-        self.as_ref().map(|node| node.child(path)).unwrap_or(None)
-    }
-
-    fn child_at(&self, i: u32) -> Option<Box<Self::Item>> {
-        self.as_ref().map(|node| node.child_at(i)).unwrap_or(None)
-    }
-
-    fn len(&self) -> u32 {
-        self.as_ref().map(|node| node.len()).unwrap_or(0)
-    }
-
-    fn dtype(&self) -> Result<Option<Dtype>> {
-        match self {
-            Some(n) => n.dtype(),
-            None => Ok(None),
-        }
-    }
-
-    fn int32(&self) -> Result<Option<i32>> {
-        match self {
-            Some(n) => n.int32(),
-            None => Ok(None),
-        }
-    }
-
-    fn int64(&self) -> Result<Option<i64>> {
-        match self {
-            Some(n) => n.int64(),
-            None => Ok(None),
-        }
-    }
-
-    fn float32(&self) -> Result<Option<f32>> {
-        match self {
-            Some(n) => n.float32(),
-            None => Ok(None),
-        }
-    }
-
-    fn float64(&self) -> Result<Option<f64>> {
-        match self {
-            Some(n) => n.float64(),
-            None => Ok(None),
-        }
-    }
-
-    fn str(&self) -> Result<Option<&'static str>> {
-        match self {
-            Some(n) => n.str(),
-            None => Ok(None),
-        }
-    }
-
-    fn name(&self) -> Result<Option<&'static str>> {
-        match self {
-            Some(n) => n.name(),
-            None => Ok(None),
-        }
-    }
-
-    fn vex_len(&self) -> Result<u32> {
-        match self {
-            Some(n) => n.vex_len(),
-            None => Ok(0),
+            let len = w as usize * h as usize * 4;
+            let data = Cow::Borrowed(std::slice::from_raw_parts(ret, len));
+            Ok(ImageBuffer { width: w, height: h, depth: d, scale: s, data })
         }
     }
 
-    fn vec(&self) -> Result<Option<glam::Vec2>> {
-        match self {
-            Some(n) => n.vec(),
-            None => Ok(None),
+    fn audio(&self) -> Result<AudioBuffer<'_>> {
+        self.expect(&[Dtype::AO])?;
+        unsafe {
+            let mut size: wz_uint32_t = 0;
+            let mut ms: wz_uint32_t = 0;
+            let mut format: wz_uint16_t = 0;
+            let ret = wz_get_ao(&mut size, &mut ms, &mut format, self.pointer.as_ptr());
+            if ret.is_null() {
+                return Err(self.ffi("wz_get_ao"));
+            }
+            let data = std::slice::from_raw_parts(ret, size as usize);
+            // a sound handed out as a WAV file, as libwz does for PCM, has its header split off like with `pure`
+            let (data, wave_format) = match split_wav(data) {
+                Some((wave_format, samples)) => (&data[samples], Some(wave_format)),
+                None => (data, None),
+            };
+            Ok(AudioBuffer { size: data.len() as u32, ms, format, data: Cow::Borrowed(data), wave_format })
         }
     }
+}
 
-    fn img<'a>(&self) -> Result<Option<ImageBuffer<'a>>> {
-        match self {
-            Some(n) => n.img(),
-            None => Ok(None),
-        }
+#[cfg(all(test, feature = "pure"))]
+mod tests {
+    use super::*;
+    use crate::pure::{fixture, Region};
+
+    #[test]
+    fn open_bytes_removes_temp_file() {
+        let ctx = WzCtx::new().unwrap();
+        let file = ctx.open_bytes(Arc::from(fixture::bytes(Region::Gms))).unwrap();
+        let temp = file.temp.as_ref().unwrap().0.clone();
+        assert!(temp.exists());
+        assert_eq!(file.open_root().child("Test.img/int").int32(), Ok(100_000));
+        drop(file);
+        assert!(!temp.exists());
+        // a file libwz rejects removes its copy at once
+        let rejected = ctx.open_reader(std::io::Cursor::new(b"not a wz file".to_vec()));
+        assert_eq!(rejected.err(), Some(WzError::FileNotOpen { path: String::new() }));
+        let prefix = format!("wz-{}-", std::process::id());
+        let left = std::fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            .count();
+        assert_eq!(left, 0);
+    }
+
+    fn _assert<T: Send + Sync>() {}
+
+    #[test]
+    fn shared_file_across_threads() {
+        _assert::<SharedWzFile>();
+        let path = std::env::temp_dir().join(format!("wz-shared-{}.wz", std::process::id()));
+        std::fs::write(&path, fixture::bytes(Region::Gms)).unwrap();
+        let file = Arc::new(SharedWzFile::open(path.to_str().unwrap()).unwrap());
+        let threads: Vec<_> = ["Test.img/int", "Mob/Mob.img/hp"]
+            .into_iter()
+            .map(|child| {
+                let file = Arc::clone(&file);
+                std::thread::spawn(move || {
+                    (0..100).map(|_| file.with_root(|root| root.child(child).int32()).unwrap()).last()
+                })
+            })
+            .collect();
+        let values: Vec<_> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+        assert_eq!(values, [Some(Ok(100_000)), Some(Ok(10))]);
+        drop(file);
+        std::fs::remove_file(&path).unwrap();
     }
 }
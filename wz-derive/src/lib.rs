@@ -0,0 +1,207 @@
+//! `#[derive(FromWzNode)]` for the `wz` crate, re-exported by it with feature `derive`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, ExprPath, Field, Fields, LitStr, Path, Token, Type,
+};
+
+/// Implement `wz::from_node::FromWzNode` for a struct with named fields,
+/// reading each field from the child node of the same name.
+///
+/// The container attribute `#[wz(crate = "path")]` names the `wz` crate when it is not `::wz`,
+/// e.g. when renamed in `Cargo.toml`.
+///
+/// Field attributes:
+///
+/// * `#[wz(rename = "maxHP")]` reads the child at another name or path, e.g. `"info/level"`.
+/// * `#[wz(default)]` uses [`Default::default`] when the child is missing,
+///   `#[wz(default = "path::to_fn")]` calls the function instead.
+/// * `#[wz(optional)]` on a field of type `Option<T>` reads [`None`] when the child is missing,
+///   which is the default for fields spelled `Option<T>` without `default`. Other types are an error.
+#[proc_macro_derive(FromWzNode, attributes(wz))]
+pub fn derive_from_wz_node(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+/// What to do when the child of a field is missing.
+enum Missing {
+    Fail,
+    Default,
+    DefaultWith(ExprPath),
+    None,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new(input.span(), "FromWzNode needs a struct with named fields")),
+        },
+        _ => return Err(Error::new(input.span(), "FromWzNode can only be derived for structs")),
+    };
+    let krate = crate_path(input)?;
+    let reads = fields.iter().map(|field| read_field(field, &krate)).collect::<syn::Result<Vec<_>>>()?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #krate::from_node::FromWzNode for #name #ty_generics #where_clause {
+            fn from_wz_node<N: #krate::node::MapleNode + ?Sized>(node: &N) -> #krate::error::Result<Self>
+            where
+                N::Item: #krate::node::MapleNode<Item = N::Item>,
+            {
+                ::std::result::Result::Ok(#name { #(#reads),* })
+            }
+        }
+    })
+}
+
+/// Get the path of the `wz` crate from `#[wz(crate = "path")]`, `::wz` by default.
+fn crate_path(input: &DeriveInput) -> syn::Result<Path> {
+    let mut krate = syn::parse_quote!(::wz);
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("wz")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                krate = meta.value()?.parse::<LitStr>()?.parse::<Path>()?;
+                Ok(())
+            } else {
+                Err(meta.error("expected `crate`"))
+            }
+        })?;
+    }
+    Ok(krate)
+}
+
+/// Generate `field: value` reading a field from its child.
+fn read_field(field: &Field, krate: &Path) -> syn::Result<TokenStream2> {
+    let ident = field.ident.as_ref().expect("named field");
+    let mut child = ident.to_string().trim_start_matches("r#").to_owned();
+    let mut missing = None;
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("wz")) {
+        attr.parse_nested_meta(|meta| {
+            let set = |missing: &mut Option<Missing>, value| {
+                if missing.is_some() {
+                    return Err(meta.error("only one of `default` and `optional` is allowed"));
+                }
+                *missing = Some(value);
+                Ok(())
+            };
+            if meta.path.is_ident("rename") {
+                child = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else if meta.path.is_ident("default") {
+                if meta.input.peek(Token![=]) {
+                    let function = meta.value()?.parse::<LitStr>()?.parse::<ExprPath>()?;
+                    set(&mut missing, Missing::DefaultWith(function))
+                } else {
+                    set(&mut missing, Missing::Default)
+                }
+            } else if meta.path.is_ident("optional") {
+                if !is_option(&field.ty) {
+                    return Err(Error::new_spanned(field, "`optional` needs a field of type `Option<T>`"));
+                }
+                set(&mut missing, Missing::None)
+            } else {
+                Err(meta.error("expected `rename`, `default` or `optional`"))
+            }
+        })?;
+    }
+    let missing = missing.unwrap_or(if is_option(&field.ty) { Missing::None } else { Missing::Fail });
+    let read = quote!(#krate::from_node::FromWzNode::from_wz_node(&*child)?);
+    let value = match missing {
+        Missing::Fail => quote!({
+            let child = #krate::node::MapleNode::child(node, #child)?;
+            #read
+        }),
+        Missing::Default => quote!(match #krate::from_node::find_child(node, #child)? {
+            ::std::option::Option::Some(child) => #read,
+            ::std::option::Option::None => ::std::default::Default::default(),
+        }),
+        Missing::DefaultWith(function) => quote!(match #krate::from_node::find_child(node, #child)? {
+            ::std::option::Option::Some(child) => #read,
+            ::std::option::Option::None => #function(),
+        }),
+        Missing::None => quote!(match #krate::from_node::find_child(node, #child)? {
+            ::std::option::Option::Some(child) => ::std::option::Option::Some(#read),
+            ::std::option::Option::None => ::std::option::Option::None,
+        }),
+    };
+    Ok(quote!(#ident: #value))
+}
+
+/// Whether a type is spelled `Option<..>`, so that its field reads [`None`] when the child is missing.
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(ty) if ty.qself.is_none() => {
+            ty.path.segments.last().is_some_and(|segment| segment.ident == "Option")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn error(input: DeriveInput) -> String {
+        expand(&input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn option_fields() {
+        assert!(is_option(&parse_quote!(Option<i32>)));
+        assert!(is_option(&parse_quote!(std::option::Option<i32>)));
+        assert!(!is_option(&parse_quote!(Vec<Option<i32>>)));
+        assert!(!is_option(&parse_quote!(<T as Trait>::Option)));
+    }
+
+    #[test]
+    fn crate_path() {
+        let tokens = expand(&parse_quote!(
+            #[wz(crate = "renamed")]
+            struct Mob {
+                level: i32,
+            }
+        ))
+        .unwrap()
+        .to_string();
+        assert!(tokens.contains("renamed :: from_node :: FromWzNode"));
+        assert!(!tokens.contains(":: wz ::"));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(error(parse_quote!(
+            enum Mob {}
+        ))
+        .contains("structs"));
+        assert!(error(parse_quote!(
+            struct Mob(i32);
+        ))
+        .contains("named fields"));
+        assert!(error(parse_quote!(
+            struct Mob {
+                #[wz(default, optional)]
+                level: Option<i32>,
+            }
+        ))
+        .contains("only one"));
+        assert!(error(parse_quote!(
+            struct Mob {
+                #[wz(skip)]
+                level: i32,
+            }
+        ))
+        .contains("expected `rename`"));
+        assert!(error(parse_quote!(
+            struct Mob {
+                #[wz(optional)]
+                level: i32,
+            }
+        ))
+        .contains("`Option<T>`"));
+    }
+}